@@ -0,0 +1,85 @@
+//! Dispatches requests to an application with 200 routes, with and
+//! without a rate limiter that limits one of them.
+//!
+//! Compare revisions with criterion's baselines, e.g. run
+//! `cargo bench --bench routes -- --save-baseline before` on the old
+//! revision and `-- --baseline before` on the new one.
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use governor::state::keyed::DefaultKeyedStateStore;
+use governor::{Quota, RateLimiter};
+use rocket::config::{Config, LogLevel};
+use rocket::http::Method;
+use rocket::local::blocking::Client;
+use rocket::route::{self, Route};
+use rocket::{Build, Data, Request, Rocket};
+use rocket_rate_limit::{
+    ConstantKeyFilter, RateLimit, RateLimitConfig,
+};
+use std::num::NonZeroU32;
+
+const ROUTES: usize = 200;
+
+fn ok<'r>(req: &'r Request<'_>, _: Data<'r>) -> route::BoxFuture<'r> {
+    route::Outcome::from(req, "ok").pin()
+}
+
+/// An application with the routes `/route0` to `/route199`, named
+/// like their path.
+fn app() -> Rocket<Build> {
+    let routes: Vec<Route> = (0..ROUTES)
+        .map(|i| {
+            let mut route =
+                Route::new(Method::Get, &format!("/route{}", i), ok);
+            route.name = Some(format!("route{}", i).into());
+            route
+        })
+        .collect();
+
+    let config = Config {
+        log_level: LogLevel::Off,
+        ..Config::debug_default()
+    };
+
+    rocket::custom(config).mount("/", routes)
+}
+
+/// A rate limiter for `/route0` that never rejects the benchmark.
+fn rate_limit() -> RateLimit<String, DefaultKeyedStateStore<String>> {
+    let mut rate_limit = RateLimit::default();
+
+    rate_limit.add(
+        "route0",
+        vec![RateLimitConfig::new(
+            RateLimiter::keyed(Quota::per_second(
+                NonZeroU32::new(1_000_000).unwrap(),
+            )),
+            Box::new(ConstantKeyFilter::new("everyone".to_string())),
+        )],
+    );
+
+    rate_limit
+}
+
+fn routes(c: &mut Criterion) {
+    let plain = Client::untracked(app()).unwrap();
+    let limited =
+        Client::untracked(app().attach(rate_limit())).unwrap();
+
+    let mut group = c.benchmark_group("200 routes");
+
+    group.bench_function("without rate limiter", |b| {
+        b.iter(|| plain.get("/route199").dispatch())
+    });
+    group.bench_function("route without limits", |b| {
+        b.iter(|| limited.get("/route199").dispatch())
+    });
+    group.bench_function("limited route", |b| {
+        b.iter(|| limited.get("/route0").dispatch())
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, routes);
+criterion_main!(benches);
@@ -0,0 +1,224 @@
+//! Procedural macros of `rocket_rate_limit`, see its `macros` module.
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::{format_ident, quote};
+use syn::meta::ParseNestedMeta;
+use syn::punctuated::Punctuated;
+use syn::{parse_macro_input, Expr, ItemFn, LitStr, Path, Token};
+
+// Shared with `QuotaSpec::from_str`, so both accept the same quotas.
+#[path = "../../src/quota_grammar.rs"]
+mod quota_grammar;
+
+/// A single `#[rate_limit(...)]` attribute.
+#[derive(Default)]
+struct Limit {
+    /// Burst size and period in milliseconds.
+    quota: Option<(u32, u64)>,
+    filter: Option<Expr>,
+    priority: Option<Expr>,
+}
+
+impl Limit {
+    fn parse(&mut self, meta: ParseNestedMeta) -> syn::Result<()> {
+        if meta.path.is_ident("quota") {
+            self.quota = Some(parse_quota(&meta.value()?.parse()?)?);
+        } else if meta.path.is_ident("by") {
+            self.filter = Some(meta.value()?.parse()?);
+        } else if meta.path.is_ident("priority") {
+            self.priority = Some(meta.value()?.parse()?);
+        } else {
+            return Err(
+                meta.error("expected `quota`, `by` or `priority`")
+            );
+        }
+
+        Ok(())
+    }
+
+    fn expand(
+        self,
+        attr: &TokenStream2,
+    ) -> syn::Result<TokenStream2> {
+        let Some((burst, per)) = self.quota else {
+            return Err(syn::Error::new_spanned(
+                attr,
+                "missing `quota`, e.g. `quota = \"10/min\"`",
+            ));
+        };
+
+        let filter = match self.filter {
+            Some(Expr::Path(path)) if path.path.is_ident("ip") => {
+                quote!(::rocket_rate_limit::IpKeyFilter)
+            }
+            Some(filter) => quote!(#filter),
+            None => {
+                return Err(syn::Error::new_spanned(
+                    attr,
+                    "missing `by`, e.g. `by = ip`",
+                ))
+            }
+        };
+
+        let priority = self
+            .priority
+            .map(|priority| quote!(.priority(#priority)));
+
+        Ok(quote! {
+            .limit(
+                ::rocket_rate_limit::governor::Quota::try_from(
+                    ::rocket_rate_limit::QuotaSpec {
+                        per: ::std::time::Duration::from_millis(#per),
+                        burst: #burst,
+                    },
+                )
+                .expect("quota was checked by #[rate_limit]"),
+                #filter,
+            )
+            #priority
+        })
+    }
+}
+
+/// Parses quotas like `10/min` or `100/15m` into the burst and the
+/// period in milliseconds.
+fn parse_quota(lit: &LitStr) -> syn::Result<(u32, u64)> {
+    quota_grammar::parse(&lit.value())
+        .map_err(|message| syn::Error::new(lit.span(), message))
+}
+
+/// Whether `attr` is a further `#[rate_limit]`, written as a suffix
+/// of `rocket_rate_limit::macros::rate_limit`.
+///
+/// Macros only see paths, so another crate's `rate_limit` imported
+/// under the same name is taken for this one, and this one renamed
+/// on import isn't recognized.
+///
+fn is_limit(attr: &syn::Attribute) -> bool {
+    const PATH: [&str; 3] =
+        ["rocket_rate_limit", "macros", "rate_limit"];
+
+    let segments = &attr.path().segments;
+    segments.len() <= PATH.len()
+        && segments
+            .iter()
+            .rev()
+            .zip(PATH.iter().rev())
+            .all(|(segment, name)| segment.ident == *name)
+}
+
+/// Name of the module a `#[rate_limit]` handler's limits are kept in.
+fn limits_module(handler: &syn::Ident) -> syn::Ident {
+    format_ident!("__rate_limit_{}", handler)
+}
+
+#[proc_macro_attribute]
+pub fn rate_limit(
+    args: TokenStream,
+    item: TokenStream,
+) -> TokenStream {
+    let mut handler = parse_macro_input!(item as ItemFn);
+    let attr = TokenStream2::from(args.clone());
+
+    let mut first = Limit::default();
+    let parser = syn::meta::parser(|meta| first.parse(meta));
+    parse_macro_input!(args with parser);
+
+    let mut limits = vec![(first, attr)];
+
+    // Further `#[rate_limit]` attributes of the handler are expanded
+    // here too, so the limits end up in a single module.
+    let mut error = None;
+    handler.attrs.retain(|attr| {
+        let is_limit = is_limit(attr);
+
+        if is_limit {
+            let mut limit = Limit::default();
+
+            match attr.parse_nested_meta(|meta| limit.parse(meta)) {
+                Ok(()) => limits.push((limit, quote!(#attr))),
+                Err(err) => error = Some(err),
+            }
+        }
+
+        !is_limit
+    });
+
+    if let Some(error) = error {
+        return error.to_compile_error().into();
+    }
+
+    let limits = match limits
+        .into_iter()
+        .map(|(limit, attr)| limit.expand(&attr))
+        .collect::<syn::Result<Vec<_>>>()
+    {
+        Ok(limits) => limits,
+        Err(error) => return error.to_compile_error().into(),
+    };
+
+    let vis = &handler.vis;
+    let name = handler.sig.ident.to_string();
+    let module = limits_module(&handler.sig.ident);
+
+    quote! {
+        #handler
+
+        #[doc(hidden)]
+        #vis mod #module {
+            use super::*;
+
+            pub const NAME: &str = #name;
+
+            pub fn limits(
+                route: ::rocket_rate_limit::RouteBuilder<
+                    ::std::string::String,
+                    ::rocket_rate_limit::governor::state::keyed::DefaultKeyedStateStore<
+                        ::std::string::String,
+                    >,
+                    ::rocket_rate_limit::governor::clock::DefaultClock,
+                >,
+            ) -> ::rocket_rate_limit::RouteBuilder<
+                ::std::string::String,
+                ::rocket_rate_limit::governor::state::keyed::DefaultKeyedStateStore<
+                    ::std::string::String,
+                >,
+                ::rocket_rate_limit::governor::clock::DefaultClock,
+            > {
+                route #(#limits)*
+            }
+        }
+    }
+    .into()
+}
+
+#[proc_macro]
+pub fn rate_limits(input: TokenStream) -> TokenStream {
+    let handlers = parse_macro_input!(
+        input with Punctuated::<Path, Token![,]>::parse_terminated
+    );
+
+    let modules: Vec<Path> = handlers
+        .into_iter()
+        .map(|mut path| {
+            let last =
+                path.segments.last_mut().expect("non-empty path");
+            last.ident = limits_module(&last.ident);
+            path
+        })
+        .collect();
+    let names = modules.iter().map(|module| quote!(#module::NAME));
+
+    quote! {
+        {
+            let builder = ::rocket_rate_limit::RateLimit::builder();
+            #(
+                let builder = #modules::limits(builder.route(#names))
+                    .finish();
+            )*
+            builder.build()
+        }
+    }
+    .into()
+}
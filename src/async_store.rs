@@ -0,0 +1,60 @@
+use crate::RateLimitStatus;
+use std::num::NonZeroU32;
+
+/// A keyed store that checks requests asynchronously.
+///
+/// Governor's state stores are synchronous, so network-backed stores
+/// block the runtime while they wait for a response. Stores that
+/// implement this trait are awaited instead, and keep track of their
+/// quotas themselves. Use them with [RateLimitConfig::with_store]:
+///
+/// ```no_run
+///# use rocket_rate_limit::{AsyncKeyedStore, RateLimitStatus, StoreOutcome};
+///# use std::num::NonZeroU32;
+///# use std::time::Duration;
+/// struct RemoteStore;
+///
+/// #[rocket::async_trait]
+/// impl AsyncKeyedStore<String> for RemoteStore {
+///     async fn check(&self, key: &String, cost: NonZeroU32) -> StoreOutcome {
+///         // Ask the remote service, e.g. with an atomic script.
+///         StoreOutcome::Allowed(RateLimitStatus::new(
+///             10,
+///             9,
+///             Duration::from_secs(1),
+///             Duration::from_secs(10),
+///         ))
+///     }
+/// }
+/// ```
+///
+/// [RateLimitConfig::with_store]: crate::RateLimitConfig::with_store
+///
+#[rocket::async_trait]
+pub trait AsyncKeyedStore<K> {
+    /// Consumes `cost` cells of the bucket of `key`, if they are
+    /// available.
+    ///
+    async fn check(&self, key: &K, cost: NonZeroU32) -> StoreOutcome;
+}
+
+/// Result of checking a request against a quota.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StoreOutcome {
+    /// The request was counted.
+    Allowed(RateLimitStatus),
+    /// The bucket doesn't have enough cells left. The
+    /// [reset](RateLimitStatus::reset) of the status is the time until
+    /// the request would be allowed.
+    Limited(RateLimitStatus),
+    /// The cost of the request is greater than the burst size, so it
+    /// can never be allowed.
+    ExceedsBurst { burst_size: u32 },
+    /// The store couldn't be reached, so the request wasn't checked.
+    /// Log the cause, the request is handled as
+    /// [RateLimit::on_store_error] says.
+    ///
+    /// [RateLimit::on_store_error]: crate::RateLimit::on_store_error
+    ///
+    Unavailable,
+}
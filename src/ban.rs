@@ -0,0 +1,109 @@
+use governor::clock::Reference;
+use governor::nanos::Nanos;
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Bans keys that are rate limited too often, see
+/// [RateLimit::ban_policy](crate::RateLimit::ban_policy).
+pub(crate) struct BanPolicy<K, P> {
+    violations: u32,
+    within: Duration,
+    ban_for: Duration,
+    keys: Mutex<HashMap<K, Violations<P>>>,
+}
+
+/// Violations of a key in the current window.
+struct Violations<P> {
+    count: u32,
+    since: P,
+    banned_until: Option<P>,
+}
+
+impl<P: Reference> Violations<P> {
+    fn window_ended(&self, within: Duration, now: P) -> bool {
+        self.since + Nanos::from(within) <= now
+    }
+
+    fn is_banned(&self, now: P) -> bool {
+        self.banned_until.is_some_and(|until| until > now)
+    }
+}
+
+impl<K, P> BanPolicy<K, P>
+where
+    K: Eq + Clone + Hash,
+    P: Reference,
+{
+    pub(crate) fn new(
+        violations: u32,
+        within: Duration,
+        ban_for: Duration,
+    ) -> Self {
+        BanPolicy {
+            violations,
+            within,
+            ban_for,
+            keys: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Returns the remaining ban time of `key`, if it's banned.
+    pub(crate) fn banned(&self, key: &K, now: P) -> Option<Duration> {
+        let mut keys = self.keys.lock().unwrap();
+        let entry = keys.get(key)?;
+
+        match entry.banned_until {
+            Some(until) if until > now => {
+                Some(until.duration_since(now).into())
+            }
+            _ => {
+                if entry.window_ended(self.within, now) {
+                    keys.remove(key);
+                }
+
+                None
+            }
+        }
+    }
+
+    /// Lifts the ban of `key` and forgets its violations.
+    pub(crate) fn clear(&self, key: &K) {
+        self.keys.lock().unwrap().remove(key);
+    }
+
+    /// Records that `key` was rate limited, and bans it once it was
+    /// rate limited too often.
+    pub(crate) fn violated(&self, key: &K, now: P) {
+        let mut keys = self.keys.lock().unwrap();
+
+        // Drop keys whose bans and windows ended, so the map doesn't
+        // grow forever.
+        let within = self.within;
+        keys.retain(|_, entry| {
+            entry.is_banned(now) || !entry.window_ended(within, now)
+        });
+
+        let entry = keys.entry(key.clone()).or_insert(Violations {
+            count: 0,
+            since: now,
+            banned_until: None,
+        });
+
+        if entry.window_ended(self.within, now) {
+            entry.count = 0;
+            entry.since = now;
+        }
+
+        entry.count += 1;
+
+        if entry.count <= self.violations {
+            return;
+        }
+
+        entry.count = 0;
+        entry.since = now;
+        entry.banned_until = Some(now + Nanos::from(self.ban_for));
+    }
+}
@@ -0,0 +1,161 @@
+use crate::{KeyFilter, RateLimit, RateLimitConfig};
+use governor::clock::Clock;
+use governor::state::keyed::KeyedStateStore;
+use governor::{Quota, RateLimiter};
+use std::hash::Hash;
+use std::num::NonZeroU32;
+
+/// Builds a [RateLimit] route by route.
+///
+/// An alternative to the `rate_limit!` macro, for configs that are
+/// e.g. loaded at runtime:
+///
+/// ```no_run
+///# use governor::Quota;
+///# use governor::state::keyed::DefaultKeyedStateStore;
+///# use rocket_rate_limit::{IpKeyFilter, RateLimit};
+///# use std::num::NonZeroU32;
+///# let (quota, premium_quota) = (
+///#     Quota::per_second(NonZeroU32::new(1).unwrap()),
+///#     Quota::per_second(NonZeroU32::new(5).unwrap()),
+///# );
+/// let rate_limit: RateLimit<String, DefaultKeyedStateStore<String>> =
+///     RateLimit::builder()
+///         .route("search")
+///         .limit(premium_quota, IpKeyFilter)
+///         .priority(3)
+///         .limit(quota, IpKeyFilter)
+///         .finish()
+///         .build();
+/// ```
+///
+pub struct RateLimitBuilder<K, S, C>
+where
+    K: Eq + Clone + Hash,
+    S: KeyedStateStore<K>,
+    C: Clock,
+{
+    rate_limit: RateLimit<K, S, C>,
+}
+
+impl<K, S, C> RateLimitBuilder<K, S, C>
+where
+    K: Eq + Clone + Hash,
+    S: KeyedStateStore<K>,
+    C: Clock,
+{
+    pub(crate) fn new(rate_limit: RateLimit<K, S, C>) -> Self {
+        RateLimitBuilder { rate_limit }
+    }
+
+    /// Starts adding configs to a route.
+    pub fn route<R: Into<String>>(
+        self,
+        route_name: R,
+    ) -> RouteBuilder<K, S, C> {
+        RouteBuilder {
+            builder: self,
+            route_name: route_name.into(),
+            configs: Vec::new(),
+        }
+    }
+
+    pub fn build(self) -> RateLimit<K, S, C> {
+        self.rate_limit
+    }
+}
+
+/// Adds configs to a route of a [RateLimitBuilder].
+pub struct RouteBuilder<K, S, C>
+where
+    K: Eq + Clone + Hash,
+    S: KeyedStateStore<K>,
+    C: Clock,
+{
+    builder: RateLimitBuilder<K, S, C>,
+    route_name: String,
+    configs: Vec<RateLimitConfig<K, S, C>>,
+}
+
+impl<K, S, C> RouteBuilder<K, S, C>
+where
+    K: Eq + Clone + Hash,
+    S: KeyedStateStore<K>,
+    C: Clock,
+{
+    /// Adds a config.
+    pub fn config(
+        mut self,
+        config: RateLimitConfig<K, S, C>,
+    ) -> Self {
+        self.configs.push(config);
+        self
+    }
+
+    /// Adds a config with a keyed limiter for `quota`.
+    ///
+    /// The limiter uses a new default store, and the clock of the
+    /// rate limiter.
+    ///
+    pub fn limit<F>(self, quota: Quota, filter: F) -> Self
+    where
+        S: Default,
+        F: KeyFilter<K> + Send + Sync + 'static,
+    {
+        let limiter = RateLimiter::new(
+            quota,
+            S::default(),
+            &self.builder.rate_limit.clock,
+        );
+
+        self.config(
+            RateLimitConfig::new(limiter, Box::new(filter))
+                .known_burst(quota.burst_size()),
+        )
+    }
+
+    /// Sets the priority of the last added config.
+    ///
+    /// # Panics
+    ///
+    /// Panics if no config was added to the route yet.
+    ///
+    pub fn priority(mut self, priority: u32) -> Self {
+        let config = self
+            .configs
+            .pop()
+            .expect("priority must follow a config of the route");
+
+        self.configs.push(config.priority(priority));
+        self
+    }
+
+    /// Sets how many cells of the quota each request to the last
+    /// added config consumes, see [RateLimitConfig::cost].
+    ///
+    /// # Panics
+    ///
+    /// Panics if no config was added to the route yet, or if `cost` is
+    /// greater than the known burst size of the quota of the config,
+    /// since such requests could never succeed.
+    ///
+    pub fn cost(mut self, cost: NonZeroU32) -> Self {
+        let config = self
+            .configs
+            .pop()
+            .expect("cost must follow a config of the route");
+
+        self.configs.push(config.cost(cost));
+        self
+    }
+
+    /// Adds the configs to the route, and returns to the rate
+    /// limiter.
+    ///
+    pub fn finish(self) -> RateLimitBuilder<K, S, C> {
+        let mut builder = self.builder;
+
+        builder.rate_limit.add(self.route_name, self.configs);
+        builder
+    }
+}
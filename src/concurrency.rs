@@ -0,0 +1,188 @@
+use crate::KeyFilter;
+use rocket::tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use rocket::tokio::time;
+use rocket::Request;
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+type Semaphores<K> = Arc<Mutex<HashMap<K, Arc<Semaphore>>>>;
+
+/// Limits the number of requests per key that are handled at the
+/// same time.
+///
+/// Unlike [RateLimitConfig](crate::RateLimitConfig), this doesn't
+/// limit requests over time, e.g. it can allow at most 3 concurrent
+/// uploads per user:
+///
+/// ```no_run
+///# use rocket_rate_limit::{ConcurrencyLimit, IpKeyFilter, RateLimit};
+///# use rocket_rate_limit::governor::state::keyed::DefaultKeyedStateStore;
+///# use std::time::Duration;
+///# let mut rate_limit = RateLimit::<String, DefaultKeyedStateStore<String>>::default();
+/// rate_limit.add_concurrency("upload", vec![
+///     ConcurrencyLimit::new(3, Box::new(IpKeyFilter))
+///         .timeout(Duration::from_secs(1)),
+/// ]);
+/// ```
+///
+/// A permit is taken when the request arrives and given back once
+/// the response is ready. If no permit becomes available within the
+/// timeout, the request is rejected.
+///
+pub struct ConcurrencyLimit<K> {
+    max: usize,
+    timeout: Duration,
+    filter: Box<dyn KeyFilter<K> + Send + Sync>,
+    semaphores: Semaphores<K>,
+}
+
+impl<K> ConcurrencyLimit<K>
+where
+    K: Eq + Clone + Hash,
+{
+    /// Allows at most `max` concurrent requests per key.
+    pub fn new(
+        max: usize,
+        filter: Box<dyn KeyFilter<K> + Send + Sync>,
+    ) -> Self {
+        ConcurrencyLimit {
+            max,
+            timeout: Duration::ZERO,
+            filter,
+            semaphores: Arc::default(),
+        }
+    }
+
+    /// Sets how long to wait for a permit before rejecting the
+    /// request.
+    ///
+    /// Defaults to zero, i.e. requests are rejected right away. The
+    /// timeout is also used as the `Retry-After` of rejections, but
+    /// never less than a second, so clients don't retry at once.
+    ///
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    pub(crate) fn retry_after(&self) -> Duration {
+        self.timeout.max(Duration::from_secs(1))
+    }
+
+    /// Takes a permit for the request, unless its key is `exempt`.
+    pub(crate) async fn acquire(
+        &self,
+        req: &Request<'_>,
+        exempt: impl Fn(&K) -> bool,
+    ) -> Acquired<K> {
+        let Some(key) = self.filter.key(req).await else {
+            return Acquired::Skipped;
+        };
+
+        if exempt(&key) {
+            return Acquired::Skipped;
+        }
+
+        let semaphore = self
+            .semaphores
+            .lock()
+            .unwrap()
+            .entry(key.clone())
+            .or_insert_with(|| Arc::new(Semaphore::new(self.max)))
+            .clone();
+
+        let permit = if self.timeout.is_zero() {
+            semaphore.try_acquire_owned().ok()
+        } else {
+            time::timeout(self.timeout, semaphore.acquire_owned())
+                .await
+                .ok()
+                .and_then(Result::ok)
+        };
+
+        let permit = Permit {
+            key,
+            permit,
+            semaphores: self.semaphores.clone(),
+        };
+
+        if permit.permit.is_some() {
+            Acquired::Permit(permit)
+        } else {
+            // Dropping the empty permit cleans up the semaphore.
+            Acquired::Rejected
+        }
+    }
+}
+
+pub(crate) enum Acquired<K>
+where
+    K: Eq + Hash,
+{
+    /// The limit doesn't apply to the request.
+    Skipped,
+    Permit(Permit<K>),
+    Rejected,
+}
+
+/// A permit held for the duration of a request.
+pub(crate) struct Permit<K>
+where
+    K: Eq + Hash,
+{
+    key: K,
+    permit: Option<OwnedSemaphorePermit>,
+    semaphores: Semaphores<K>,
+}
+
+impl<K> Drop for Permit<K>
+where
+    K: Eq + Hash,
+{
+    fn drop(&mut self) {
+        drop(self.permit.take());
+
+        // Remove semaphores nobody holds or waits for, so the map
+        // doesn't grow forever. Permits and waiters keep a reference.
+        let mut semaphores = self.semaphores.lock().unwrap();
+
+        if semaphores.get(&self.key).is_some_and(|semaphore| {
+            Arc::strong_count(semaphore) == 1
+        }) {
+            semaphores.remove(&self.key);
+        }
+    }
+}
+
+/// The permits of a request, stored in the request-local cache.
+///
+/// Every permit is tagged with the ID of the rate limiter that took
+/// it, since the cache is shared by all rate limiters with the same
+/// key type.
+///
+pub(crate) struct Permits<K>(
+    pub(crate) Mutex<Vec<(usize, Permit<K>)>>,
+)
+where
+    K: Eq + Hash;
+
+impl<K> Default for Permits<K>
+where
+    K: Eq + Hash,
+{
+    fn default() -> Self {
+        Permits(Mutex::new(Vec::new()))
+    }
+}
+
+impl<K> Permits<K>
+where
+    K: Eq + Hash,
+{
+    /// Gives back the permits taken by the rate limiter `id`.
+    pub(crate) fn release(&self, id: usize) {
+        self.0.lock().unwrap().retain(|(owner, _)| *owner != id);
+    }
+}
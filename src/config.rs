@@ -0,0 +1,262 @@
+use crate::{quota_grammar, IpKeyFilter, RateLimit};
+use governor::clock::Clock;
+use governor::state::keyed::KeyedStateStore;
+use governor::Quota;
+use rocket::figment::{self, Figment};
+use rocket::serde::{de, ser};
+use rocket::serde::{
+    Deserialize, Deserializer, Serialize, Serializer,
+};
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt;
+use std::num::NonZeroU32;
+use std::str::FromStr;
+use std::time::Duration;
+
+/// Key of the table in Rocket's configuration.
+const CONFIG_KEY: &str = "rate_limit";
+
+/// Quota of a route in Rocket's configuration.
+#[derive(Deserialize)]
+#[serde(crate = "rocket::serde", untagged)]
+enum RouteQuota {
+    /// A [QuotaSpec] like `"10/min"`.
+    Spec(String),
+    Interval {
+        /// Milliseconds it takes to replenish a single cell.
+        replenish_interval: u64,
+        /// Defaults to one.
+        burst: Option<u32>,
+    },
+}
+
+impl RouteQuota {
+    fn quota(
+        &self,
+        route_name: &str,
+    ) -> Result<Quota, figment::Error> {
+        let invalid = |reason: &dyn fmt::Display| {
+            figment::Error::from(format!(
+                "invalid rate limit for route `{}`: {}",
+                route_name, reason
+            ))
+        };
+
+        let (replenish_interval, burst) = match self {
+            RouteQuota::Spec(spec) => {
+                return spec
+                    .parse::<QuotaSpec>()
+                    .and_then(Quota::try_from)
+                    .map_err(|error| invalid(&error));
+            }
+            RouteQuota::Interval {
+                replenish_interval,
+                burst,
+            } => (*replenish_interval, *burst),
+        };
+
+        let quota = Quota::with_period(Duration::from_millis(
+            replenish_interval,
+        ))
+        .ok_or_else(|| {
+            invalid(&"`replenish_interval` must not be zero")
+        })?;
+
+        match burst {
+            Some(burst) => NonZeroU32::new(burst)
+                .map(|burst| quota.allow_burst(burst))
+                .ok_or_else(|| invalid(&"`burst` must not be zero")),
+            None => Ok(quota),
+        }
+    }
+}
+
+/// A serializable quota, e.g. for limits loaded from JSON or TOML.
+///
+/// Allows `burst` requests per `per`, like [Quota::per_minute] does
+/// per minute. Convert it with `Quota::try_from`:
+///
+/// ```no_run
+///# use governor::Quota;
+///# use rocket_rate_limit::QuotaSpec;
+///# use std::time::Duration;
+/// let spec = QuotaSpec {
+///     per: Duration::from_secs(60),
+///     burst: 100,
+/// };
+/// let quota = Quota::try_from(spec).unwrap();
+/// ```
+///
+/// It's (de)serialized as a string like `"10/min"` or `"100/15m"`,
+/// the same quotas `#[rate_limit]` takes. The period is a number,
+/// one by default, followed by `ms`, `s`, `m`, `h` or `d`, or by
+/// `sec`, `second`, `min`, `minute`, `hour` or `day`. Periods that
+/// aren't a whole number of milliseconds can't be serialized.
+///
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct QuotaSpec {
+    pub per: Duration,
+    pub burst: u32,
+}
+
+impl FromStr for QuotaSpec {
+    type Err = InvalidQuota;
+
+    /// Parses quotas like `10/min` or `100/15m`.
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let (burst, per) =
+            quota_grammar::parse(value).map_err(InvalidQuota)?;
+
+        Ok(QuotaSpec {
+            per: Duration::from_millis(per),
+            burst,
+        })
+    }
+}
+
+impl Serialize for QuotaSpec {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let millis = u64::try_from(self.per.as_millis())
+            .ok()
+            .filter(|_| self.per.subsec_nanos() % 1_000_000 == 0)
+            .ok_or_else(|| {
+                ser::Error::custom(
+                    "the period of the quota isn't a whole number of \
+                     milliseconds",
+                )
+            })?;
+
+        // Use the largest unit the period is a multiple of.
+        let (count, unit) = [
+            (24 * 60 * 60 * 1000, "d"),
+            (60 * 60 * 1000, "h"),
+            (60 * 1000, "m"),
+            (1000, "s"),
+        ]
+        .into_iter()
+        .find(|(unit, _)| millis != 0 && millis % unit == 0)
+        .map_or((millis, "ms"), |(unit, name)| (millis / unit, name));
+
+        serializer.collect_str(&format_args!(
+            "{}/{}{}",
+            self.burst, count, unit
+        ))
+    }
+}
+
+impl<'de> Deserialize<'de> for QuotaSpec {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(de::Error::custom)
+    }
+}
+
+/// Error of converting an invalid [QuotaSpec].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct InvalidQuota(&'static str);
+
+impl fmt::Display for InvalidQuota {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid quota: {}", self.0)
+    }
+}
+
+impl Error for InvalidQuota {}
+
+impl TryFrom<QuotaSpec> for Quota {
+    type Error = InvalidQuota;
+
+    fn try_from(spec: QuotaSpec) -> Result<Self, Self::Error> {
+        let burst = NonZeroU32::new(spec.burst)
+            .ok_or(InvalidQuota("`burst` must not be zero"))?;
+
+        let quota = Quota::with_period(spec.per / burst.get())
+            .ok_or(InvalidQuota(
+                "`per` must be at least `burst` nanoseconds",
+            ))?;
+
+        Ok(quota.allow_burst(burst))
+    }
+}
+
+impl<S, C> RateLimit<String, S, C>
+where
+    S: KeyedStateStore<String> + Default,
+    C: Clock + Default,
+{
+    /// Creates a rate limiter from the `rate_limit` table of Rocket's
+    /// configuration.
+    ///
+    /// Every route of the table is limited by IP address, with a
+    /// quota like [QuotaSpec] takes:
+    ///
+    /// ```toml
+    /// [default.rate_limit]
+    /// search = "10/min"
+    /// ```
+    ///
+    /// Quotas can also be given by their replenish interval:
+    ///
+    /// ```toml
+    /// [default.rate_limit.search]
+    /// # Milliseconds it takes to replenish a single request.
+    /// replenish_interval = 500
+    /// # Requests that can be made at once, one by default.
+    /// burst = 10
+    /// ```
+    ///
+    /// Use it from an ignite fairing, so invalid configurations fail
+    /// ignition:
+    ///
+    /// ```no_run
+    ///# use governor::state::keyed::DefaultKeyedStateStore;
+    ///# use rocket::fairing::AdHoc;
+    ///# use rocket_rate_limit::RateLimit;
+    /// let rocket = rocket::build().attach(AdHoc::try_on_ignite(
+    ///     "Rate Limit Config",
+    ///     |rocket| async move {
+    ///         match RateLimit::<String, DefaultKeyedStateStore<String>>::from_figment(
+    ///             rocket.figment(),
+    ///         ) {
+    ///             Ok(rate_limit) => Ok(rocket.attach(rate_limit)),
+    ///             Err(error) => {
+    ///                 eprintln!("{}", error);
+    ///                 Err(rocket)
+    ///             }
+    ///         }
+    ///     },
+    /// ));
+    /// ```
+    ///
+    /// A missing table results in a rate limiter without configs.
+    ///
+    pub fn from_figment(
+        figment: &Figment,
+    ) -> Result<Self, figment::Error> {
+        if !figment.contains(CONFIG_KEY) {
+            return Ok(RateLimit::default());
+        }
+
+        let routes: BTreeMap<String, RouteQuota> =
+            figment.extract_inner(CONFIG_KEY)?;
+
+        let mut builder = RateLimit::builder();
+
+        for (route_name, quota) in &routes {
+            builder = builder
+                .route(route_name.as_str())
+                .limit(quota.quota(route_name)?, IpKeyFilter)
+                .finish();
+        }
+
+        Ok(builder.build())
+    }
+}
@@ -0,0 +1,1043 @@
+use crate::{KeyError, KeyFilter, TryKeyFilter};
+use ipnet::IpNet;
+use rocket::futures::future::BoxFuture;
+use rocket::http::uri::Absolute;
+use rocket::request::FromRequest;
+use rocket::Request;
+use siphasher::sip::SipHasher13;
+use std::collections::HashSet;
+use std::hash::{Hash, Hasher};
+use std::marker::PhantomData;
+use std::net::{IpAddr, SocketAddr};
+
+/// Uses the client's IP address as the key.
+///
+/// IPv4-mapped IPv6 addresses (`::ffff:a.b.c.d`) are converted to
+/// IPv4, so clients of dual-stack sockets have a single bucket.
+///
+pub struct IpKeyFilter;
+
+#[rocket::async_trait]
+impl KeyFilter<String> for IpKeyFilter {
+    async fn key(&self, req: &Request<'_>) -> Option<String> {
+        req.client_ip().map(|ip| normalize_ip(ip).to_string())
+    }
+}
+
+impl IpKeyFilter {
+    /// Handles requests without a client IP address, e.g. from a Unix
+    /// socket or a misconfigured proxy, according to `policy`.
+    ///
+    /// [IpKeyFilter] skips the config for such requests, so clients
+    /// that can hide their address aren't limited at all.
+    ///
+    /// The filter is a [TryKeyFilter], so it only works with
+    /// [RateLimitConfig::try_new](crate::RateLimitConfig::try_new),
+    /// not with `RateLimitConfig::new`, the builder's `limit` or
+    /// `#[rate_limit]`:
+    ///
+    /// ```no_run
+    ///# use governor::{Quota, RateLimiter};
+    ///# use governor::state::keyed::DefaultKeyedStateStore;
+    ///# use rocket_rate_limit::{IpKeyFilter, MissingIpPolicy, RateLimit, RateLimitConfig};
+    ///# use std::num::NonZeroU32;
+    /// let mut rate_limit: RateLimit<String, DefaultKeyedStateStore<String>> =
+    ///     RateLimit::default();
+    ///
+    /// rate_limit.add("search", vec![RateLimitConfig::try_new(
+    ///     RateLimiter::keyed(Quota::per_minute(NonZeroU32::new(10).unwrap())),
+    ///     Box::new(IpKeyFilter::on_missing(MissingIpPolicy::Shared)),
+    /// )]);
+    /// ```
+    ///
+    pub fn on_missing(
+        policy: MissingIpPolicy,
+    ) -> FallbackIpKeyFilter {
+        FallbackIpKeyFilter { policy }
+    }
+}
+
+/// What [IpKeyFilter::on_missing] does with requests without a
+/// client IP address.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum MissingIpPolicy {
+    /// Skips the config, like [IpKeyFilter].
+    #[default]
+    Skip,
+    /// Counts all such requests against the `"unknown"` key, so they
+    /// share a single bucket.
+    Shared,
+    /// Rejects such requests, whether or not the config is
+    /// [fail_closed](crate::RateLimitConfig::fail_closed).
+    Reject,
+}
+
+/// [IpKeyFilter] with a policy for requests without a client IP
+/// address, see [IpKeyFilter::on_missing].
+///
+/// It's a [TryKeyFilter], so use it with
+/// [RateLimitConfig::try_new](crate::RateLimitConfig::try_new).
+///
+pub struct FallbackIpKeyFilter {
+    policy: MissingIpPolicy,
+}
+
+#[rocket::async_trait]
+impl TryKeyFilter<String> for FallbackIpKeyFilter {
+    async fn try_key(
+        &self,
+        req: &Request<'_>,
+    ) -> Result<Option<String>, KeyError> {
+        if let Some(ip) = req.client_ip() {
+            return Ok(Some(normalize_ip(ip).to_string()));
+        }
+
+        match self.policy {
+            MissingIpPolicy::Skip => Ok(None),
+            MissingIpPolicy::Shared => {
+                Ok(Some("unknown".to_string()))
+            }
+            MissingIpPolicy::Reject => Err(KeyError::reject(
+                "the request has no client IP address",
+            )),
+        }
+    }
+}
+
+/// Uses the client's IP address as the key, without converting it
+/// to a string.
+///
+/// Cheaper than [IpKeyFilter], for rate limiters keyed by `IpAddr`.
+/// IPv4-mapped addresses are converted the same way.
+///
+pub struct IpAddrKeyFilter;
+
+#[rocket::async_trait]
+impl KeyFilter<IpAddr> for IpAddrKeyFilter {
+    async fn key(&self, req: &Request<'_>) -> Option<IpAddr> {
+        req.client_ip().map(normalize_ip)
+    }
+}
+
+/// A request guard that identifies a user by a numeric id, see
+/// [UserIdKeyFilter].
+pub trait UserId {
+    fn user_id(&self) -> u64;
+}
+
+/// Uses the id of the user a request guard authenticates as the key.
+///
+/// Numeric keys avoid formatting ids into strings, for rate limiters
+/// keyed by `u64`:
+///
+/// ```no_run
+///# use governor::Quota;
+///# use governor::state::keyed::DefaultKeyedStateStore;
+///# use rocket::request::{self, FromRequest, Request};
+///# use rocket_rate_limit::{RateLimit, RateLimitConfig, UserId, UserIdKeyFilter};
+///# use std::num::NonZeroU32;
+/// struct User {
+///     id: u64,
+/// }
+///
+///# #[rocket::async_trait]
+///# impl<'r> FromRequest<'r> for User {
+///#     type Error = ();
+///#     async fn from_request(_req: &'r Request<'_>) -> request::Outcome<Self, ()> {
+///#         request::Outcome::Forward(())
+///#     }
+///# }
+/// impl UserId for User {
+///     fn user_id(&self) -> u64 {
+///         self.id
+///     }
+/// }
+///
+/// let mut rate_limit: RateLimit<u64, DefaultKeyedStateStore<u64>> =
+///     RateLimit::default();
+///
+/// rate_limit.add("upload", vec![RateLimitConfig::keyed(
+///     Quota::per_minute(NonZeroU32::new(10).unwrap()),
+///     Box::new(UserIdKeyFilter::<User>::new()),
+/// )]);
+/// ```
+///
+/// If the guard doesn't succeed, the config is skipped.
+///
+pub struct UserIdKeyFilter<G> {
+    _guard: PhantomData<fn() -> G>,
+}
+
+impl<G> UserIdKeyFilter<G> {
+    pub fn new() -> Self {
+        UserIdKeyFilter {
+            _guard: PhantomData,
+        }
+    }
+}
+
+impl<G> Default for UserIdKeyFilter<G> {
+    fn default() -> Self {
+        UserIdKeyFilter::new()
+    }
+}
+
+#[rocket::async_trait]
+impl<G> KeyFilter<u64> for UserIdKeyFilter<G>
+where
+    G: for<'r> FromRequest<'r> + UserId + Send,
+{
+    async fn key(&self, req: &Request<'_>) -> Option<u64> {
+        req.guard::<G>()
+            .await
+            .succeeded()
+            .map(|user| user.user_id())
+    }
+}
+
+/// Uses the value of a request header as the key.
+///
+/// Useful for rate limiting by API key:
+///
+/// ```no_run
+///# use rocket_rate_limit::HeaderKeyFilter;
+/// let filter = HeaderKeyFilter::new("x-api-key");
+/// ```
+///
+/// If the header is missing, the config is skipped.
+///
+pub struct HeaderKeyFilter {
+    header_name: String,
+}
+
+impl HeaderKeyFilter {
+    pub fn new<N: Into<String>>(header_name: N) -> Self {
+        HeaderKeyFilter {
+            header_name: header_name.into(),
+        }
+    }
+}
+
+#[rocket::async_trait]
+impl KeyFilter<String> for HeaderKeyFilter {
+    async fn key(&self, req: &Request<'_>) -> Option<String> {
+        req.headers()
+            .get_one(&self.header_name)
+            .map(|value| value.to_string())
+    }
+}
+
+/// Uses the token of an `Authorization: Bearer <token>` header as the
+/// key.
+///
+/// The scheme is matched case-insensitively, and whitespace around
+/// the token is ignored. If the header is missing, uses another
+/// scheme, or has an empty token, the config is skipped.
+///
+pub struct AuthBearerKeyFilter;
+
+#[rocket::async_trait]
+impl KeyFilter<String> for AuthBearerKeyFilter {
+    async fn key(&self, req: &Request<'_>) -> Option<String> {
+        let value =
+            req.headers().get_one("Authorization")?.trim_start();
+        let (scheme, token) =
+            value.split_once(char::is_whitespace)?;
+
+        if !scheme.eq_ignore_ascii_case("Bearer") {
+            return None;
+        }
+
+        let token = token.trim();
+        if token.is_empty() {
+            return None;
+        }
+
+        Some(token.to_string())
+    }
+}
+
+/// Uses the value of a cookie as the key.
+///
+/// Useful for limiting per-session actions, like password changes.
+/// If the cookie is missing, the config is skipped.
+///
+pub struct CookieKeyFilter {
+    name: String,
+    #[cfg(feature = "secrets")]
+    private: bool,
+}
+
+impl CookieKeyFilter {
+    pub fn new<N: Into<String>>(name: N) -> Self {
+        CookieKeyFilter {
+            name: name.into(),
+            #[cfg(feature = "secrets")]
+            private: false,
+        }
+    }
+
+    /// Reads the cookie from the private (encrypted) cookie jar.
+    ///
+    /// Useful for session ids, since clients can't forge them. With
+    /// an [OrFilter], visitors without a session fall back to e.g.
+    /// their IP address:
+    ///
+    /// ```no_run
+    ///# use rocket_rate_limit::{CookieKeyFilter, IpKeyFilter, OrFilter};
+    /// let filter = OrFilter::new(vec![
+    ///     Box::new(CookieKeyFilter::new("session_id").private(true)),
+    ///     Box::new(IpKeyFilter),
+    /// ]);
+    /// ```
+    ///
+    /// Requires the `secrets` feature. Private cookies are encrypted
+    /// with Rocket's `secret_key`, so set a fixed key in production:
+    /// the key generated in debug builds changes on every start,
+    /// and cookies from earlier runs then don't decrypt, which skips
+    /// the config.
+    ///
+    #[cfg(feature = "secrets")]
+    pub fn private(mut self, private: bool) -> Self {
+        self.private = private;
+        self
+    }
+}
+
+#[rocket::async_trait]
+impl KeyFilter<String> for CookieKeyFilter {
+    async fn key(&self, req: &Request<'_>) -> Option<String> {
+        #[cfg(feature = "secrets")]
+        if self.private {
+            return req
+                .cookies()
+                .get_private(&self.name)
+                .map(|cookie| cookie.value().to_string());
+        }
+
+        req.cookies()
+            .get(&self.name)
+            .map(|cookie| cookie.value().to_string())
+    }
+}
+
+/// Uses the value of a query parameter as the key.
+///
+/// The value is percent-decoded. If the parameter is repeated, the
+/// first occurrence is used. If the parameter is missing or empty,
+/// the config is skipped.
+///
+pub struct QueryKeyFilter {
+    param: String,
+}
+
+impl QueryKeyFilter {
+    pub fn new<P: Into<String>>(param: P) -> Self {
+        QueryKeyFilter {
+            param: param.into(),
+        }
+    }
+}
+
+#[rocket::async_trait]
+impl KeyFilter<String> for QueryKeyFilter {
+    async fn key(&self, req: &Request<'_>) -> Option<String> {
+        req.uri()
+            .query()?
+            .segments()
+            .find(|(name, _)| *name == self.param)
+            .map(|(_, value)| value)
+            .filter(|value| !value.is_empty())
+            .map(|value| value.to_string())
+    }
+}
+
+/// Uses the same key for every request.
+///
+/// The config then has a single bucket for everyone, e.g. to allow
+/// 100 requests per minute to a route in total:
+///
+/// ```no_run
+///# use rocket_rate_limit::ConstantKeyFilter;
+/// let filter = ConstantKeyFilter::new("search".to_string());
+/// ```
+///
+/// Unlike [RateLimitConfig::direct], this works with any keyed
+/// limiter or store, e.g. to share the bucket between instances.
+///
+/// [RateLimitConfig::direct]: crate::RateLimitConfig::direct
+///
+pub struct ConstantKeyFilter<K> {
+    key: K,
+}
+
+impl<K> ConstantKeyFilter<K> {
+    pub fn new(key: K) -> Self {
+        ConstantKeyFilter { key }
+    }
+}
+
+#[rocket::async_trait]
+impl<K> KeyFilter<K> for ConstantKeyFilter<K>
+where
+    K: Clone + Send + Sync,
+{
+    async fn key(&self, _req: &Request<'_>) -> Option<K> {
+        Some(self.key.clone())
+    }
+}
+
+/// Uses the host the request was sent to as the key.
+///
+/// Useful for serving several domains, so each domain gets its own
+/// buckets. The host is lowercased, and its port and any trailing
+/// dot are removed, so `Example.com.:443` and `example.com` share a
+/// bucket. If the request has no host, the config is skipped.
+///
+pub struct HostKeyFilter;
+
+#[rocket::async_trait]
+impl KeyFilter<String> for HostKeyFilter {
+    async fn key(&self, req: &Request<'_>) -> Option<String> {
+        let domain =
+            req.host()?.domain().as_str().trim_end_matches('.');
+
+        if domain.is_empty() {
+            return None;
+        }
+
+        Some(domain.to_ascii_lowercase())
+    }
+}
+
+/// Uses the origin of the page that sent the request as the key.
+///
+/// The origin is read from the `Origin` header, or the `Referer`
+/// header if it's missing, and has the form `scheme://host[:port]`,
+/// so `https://evil.com/a` and `https://evil.com/b` share a bucket.
+/// Useful against scrapers that rotate IP addresses, but keep
+/// embedding the same page.
+///
+/// If neither header has a valid URL, or the request comes from the
+/// same host it was sent to, the config is skipped.
+///
+pub struct OriginKeyFilter;
+
+#[rocket::async_trait]
+impl KeyFilter<String> for OriginKeyFilter {
+    async fn key(&self, req: &Request<'_>) -> Option<String> {
+        let headers = req.headers();
+        let value = headers
+            .get_one("Origin")
+            .or_else(|| headers.get_one("Referer"))?;
+
+        let url = Absolute::parse(value.trim()).ok()?;
+        let scheme = url.scheme().to_ascii_lowercase();
+        let authority = url.authority()?;
+        let host = authority.host().to_ascii_lowercase();
+
+        if host.is_empty() {
+            return None;
+        }
+
+        let same_origin = req.host().map_or(false, |own| {
+            own.domain().as_str().eq_ignore_ascii_case(&host)
+        });
+        if same_origin {
+            return None;
+        }
+
+        let default_port = match scheme.as_str() {
+            "http" => Some(80),
+            "https" => Some(443),
+            _ => None,
+        };
+
+        match authority.port() {
+            Some(port) if Some(port) != default_port => {
+                Some(format!("{}://{}:{}", scheme, host, port))
+            }
+            _ => Some(format!("{}://{}", scheme, host)),
+        }
+    }
+}
+
+/// Uses a segment of the request path as the key.
+///
+/// Useful for multi-tenant applications, where e.g. the first
+/// segment of `/acme/users` names the tenant:
+///
+/// ```no_run
+///# use rocket_rate_limit::PathSegmentKeyFilter;
+/// let filter = PathSegmentKeyFilter::new(0);
+/// ```
+///
+/// Segments are counted from zero and percent-decoded. If the path
+/// has fewer segments, the config is skipped.
+///
+pub struct PathSegmentKeyFilter {
+    index: usize,
+}
+
+impl PathSegmentKeyFilter {
+    pub fn new(index: usize) -> Self {
+        PathSegmentKeyFilter { index }
+    }
+}
+
+#[rocket::async_trait]
+impl KeyFilter<String> for PathSegmentKeyFilter {
+    async fn key(&self, req: &Request<'_>) -> Option<String> {
+        req.uri()
+            .path()
+            .segments()
+            .nth(self.index)
+            .map(|segment| segment.to_string())
+    }
+}
+
+/// Prefixes the key of another filter with the request method.
+///
+/// This gives every method of a route a separate bucket, so reads
+/// and writes are counted separately. The key has the form
+/// `"{method}:{inner}"`:
+///
+/// ```no_run
+///# use rocket_rate_limit::{IpKeyFilter, WithMethod};
+/// let filter = WithMethod(IpKeyFilter);
+/// ```
+///
+/// If the inner filter returns `None`, the config is skipped.
+///
+pub struct WithMethod<F>(pub F);
+
+#[rocket::async_trait]
+impl<F> KeyFilter<String> for WithMethod<F>
+where
+    F: KeyFilter<String> + Send + Sync,
+{
+    async fn key(&self, req: &Request<'_>) -> Option<String> {
+        let inner = self.0.key(req).await?;
+
+        Some(format!("{}:{}", req.method(), inner))
+    }
+}
+
+/// Combines the keys of two filters into a tuple key.
+///
+/// For example, `AndFilter(IpKeyFilter, HeaderKeyFilter::new(..))`
+/// limits each API key per IP, so a single IP using many keys
+/// doesn't starve others. The limiter has to be keyed on the tuple,
+/// e.g. `RateLimit<(String, String), _>`.
+///
+/// If **either** filter returns `None`, the whole config is skipped.
+///
+pub struct AndFilter<A, B>(pub A, pub B);
+
+#[rocket::async_trait]
+impl<A, B, KA, KB> KeyFilter<(KA, KB)> for AndFilter<A, B>
+where
+    A: KeyFilter<KA> + Send + Sync,
+    B: KeyFilter<KB> + Send + Sync,
+    KA: Send + 'static,
+    KB: Send + 'static,
+{
+    async fn key(&self, req: &Request<'_>) -> Option<(KA, KB)> {
+        let a = self.0.key(req).await?;
+        let b = self.1.key(req).await?;
+
+        Some((a, b))
+    }
+}
+
+/// Hashes the key of another filter, so the store only holds
+/// opaque hashes instead of e.g. API keys or IP addresses.
+///
+/// ```no_run
+///# use rocket_rate_limit::{HashedFilter, HeaderKeyFilter};
+///# let key = [0; 16];
+/// let filter =
+///     HashedFilter::new(HeaderKeyFilter::new("x-api-key"), key);
+/// ```
+///
+/// The limiter has to be keyed on `u64`. The hash is SipHash-1-3
+/// keyed with `key`, which has to be kept secret, so the hashes
+/// can't be reversed by hashing every possible input. The same key
+/// gives the same hashes across restarts and instances, so
+/// instances sharing a store have to use the same key. Keys of
+/// different inputs collide with a negligible probability.
+///
+pub struct HashedFilter<F, K> {
+    inner: F,
+    key: [u8; 16],
+    _key: PhantomData<fn() -> K>,
+}
+
+impl<F, K> HashedFilter<F, K>
+where
+    F: KeyFilter<K>,
+{
+    pub fn new(inner: F, key: [u8; 16]) -> Self {
+        HashedFilter {
+            inner,
+            key,
+            _key: PhantomData,
+        }
+    }
+}
+
+#[rocket::async_trait]
+impl<F, K> KeyFilter<u64> for HashedFilter<F, K>
+where
+    F: KeyFilter<K> + Send + Sync,
+    K: Hash + Send + 'static,
+{
+    async fn key(&self, req: &Request<'_>) -> Option<u64> {
+        let key = self.inner.key(req).await?;
+
+        let mut hasher = SipHasher13::new_with_key(&self.key);
+        key.hash(&mut hasher);
+
+        Some(hasher.finish())
+    }
+}
+
+/// Returns the key of the first filter that extracts one.
+///
+/// Useful for fallback chains, e.g. keying authenticated users by
+/// their ID and anonymous users by their IP:
+///
+/// ```no_run
+///# use rocket_rate_limit::{HeaderKeyFilter, IpKeyFilter, OrFilter};
+/// let filter = OrFilter::new(vec![
+///     Box::new(HeaderKeyFilter::new("x-api-key")),
+///     Box::new(IpKeyFilter),
+/// ]);
+/// ```
+///
+/// If all filters return `None`, the config is skipped.
+///
+pub struct OrFilter<K> {
+    filters: Vec<Box<dyn KeyFilter<K> + Send + Sync>>,
+}
+
+impl<K> OrFilter<K> {
+    pub fn new(
+        filters: Vec<Box<dyn KeyFilter<K> + Send + Sync>>,
+    ) -> Self {
+        OrFilter { filters }
+    }
+}
+
+#[rocket::async_trait]
+impl<K> KeyFilter<K> for OrFilter<K>
+where
+    K: Send + 'static,
+{
+    async fn key(&self, req: &Request<'_>) -> Option<K> {
+        for filter in &self.filters {
+            if let Some(key) = filter.key(req).await {
+                return Some(key);
+            }
+        }
+
+        None
+    }
+}
+
+/// Skips the config for some keys of another filter.
+///
+/// The opposite of an allowlist entry for a single config, e.g. to
+/// limit every API key except the ones of internal services:
+///
+/// ```no_run
+///# use rocket_rate_limit::{ExceptFilter, HeaderKeyFilter};
+///# use std::collections::HashSet;
+/// let filter = ExceptFilter::new(
+///     HeaderKeyFilter::new("x-api-key"),
+///     HashSet::from(["internal-key".to_string()]),
+/// );
+/// ```
+///
+/// If the inner filter returns `None` or an excluded key, the config
+/// is skipped.
+///
+pub struct ExceptFilter<F, K> {
+    inner: F,
+    excluded: HashSet<K>,
+}
+
+impl<F, K> ExceptFilter<F, K> {
+    pub fn new(inner: F, excluded: HashSet<K>) -> Self {
+        ExceptFilter { inner, excluded }
+    }
+}
+
+#[rocket::async_trait]
+impl<F, K> KeyFilter<K> for ExceptFilter<F, K>
+where
+    F: KeyFilter<K> + Send + Sync,
+    K: Eq + Hash + Send + Sync + 'static,
+{
+    async fn key(&self, req: &Request<'_>) -> Option<K> {
+        let key = self.inner.key(req).await?;
+
+        (!self.excluded.contains(&key)).then_some(key)
+    }
+}
+
+/// Skips the config for IP addresses in some networks, e.g. an
+/// office subnet.
+///
+/// Wraps a filter of `IpAddr` keys, like [IpAddrKeyFilter]:
+///
+/// ```no_run
+///# use rocket_rate_limit::{ExceptCidr, IpAddrKeyFilter};
+/// let filter = ExceptCidr::new(
+///     IpAddrKeyFilter,
+///     vec!["192.168.1.0/24".parse().unwrap()],
+/// );
+/// ```
+///
+/// If the inner filter returns `None` or an address in one of the
+/// networks, the config is skipped.
+///
+pub struct ExceptCidr<F> {
+    inner: F,
+    networks: Vec<IpNet>,
+}
+
+impl<F> ExceptCidr<F> {
+    pub fn new(inner: F, networks: Vec<IpNet>) -> Self {
+        ExceptCidr { inner, networks }
+    }
+}
+
+#[rocket::async_trait]
+impl<F> KeyFilter<IpAddr> for ExceptCidr<F>
+where
+    F: KeyFilter<IpAddr> + Send + Sync,
+{
+    async fn key(&self, req: &Request<'_>) -> Option<IpAddr> {
+        let ip = self.inner.key(req).await?;
+
+        (!self.networks.iter().any(|network| network.contains(&ip)))
+            .then_some(ip)
+    }
+}
+
+/// Uses the client's IP address from `X-Forwarded-For` (or other
+/// proxy headers) as the key.
+///
+/// Unlike [IpKeyFilter], this doesn't rely on Rocket's `ip_header`
+/// config and only trusts the header when the request comes from
+/// one of the `trusted_proxies`. The header is then walked from
+/// right to left for at most `depth` hops, skipping trusted
+/// proxies, and the first untrusted address is used:
+///
+/// ```no_run
+///# use rocket_rate_limit::ForwardedIpKeyFilter;
+/// let filter = ForwardedIpKeyFilter::new(
+///     vec!["10.0.0.0/8".parse().unwrap()],
+///     2,
+/// );
+/// ```
+///
+/// Without a forwarded chain, the remote address is used.
+///
+pub struct ForwardedIpKeyFilter {
+    trusted_proxies: Vec<IpNet>,
+    depth: usize,
+    client_ip_headers: Vec<String>,
+}
+
+/// Name of the header that holds the chain of forwarded addresses.
+const FORWARDED_FOR: &str = "X-Forwarded-For";
+
+impl ForwardedIpKeyFilter {
+    pub fn new(trusted_proxies: Vec<IpNet>, depth: usize) -> Self {
+        ForwardedIpKeyFilter {
+            trusted_proxies,
+            depth,
+            client_ip_headers: vec![FORWARDED_FOR.to_string()],
+        }
+    }
+
+    /// Sets the headers that hold the client's IP address, in the
+    /// order they are tried.
+    ///
+    /// Proxies like Cloudflare send the address in a header of their
+    /// own. The first header with a valid address wins, and
+    /// `X-Forwarded-For` is walked as described above:
+    ///
+    /// ```no_run
+    ///# use rocket_rate_limit::ForwardedIpKeyFilter;
+    /// let filter = ForwardedIpKeyFilter::new(
+    ///     vec!["173.245.48.0/20".parse().unwrap()],
+    ///     1,
+    /// )
+    /// .client_ip_headers(vec![
+    ///     "CF-Connecting-IP".to_string(),
+    ///     "True-Client-IP".to_string(),
+    ///     "X-Real-IP".to_string(),
+    ///     "X-Forwarded-For".to_string(),
+    /// ]);
+    /// ```
+    ///
+    /// Like `X-Forwarded-For`, the headers are only trusted for
+    /// requests from trusted proxies. Defaults to `X-Forwarded-For`
+    /// only.
+    ///
+    pub fn client_ip_headers(mut self, headers: Vec<String>) -> Self {
+        self.client_ip_headers = headers;
+        self
+    }
+
+    fn is_trusted(&self, ip: &IpAddr) -> bool {
+        self.trusted_proxies.iter().any(|net| net.contains(ip))
+    }
+
+    fn client_ip(&self, req: &Request<'_>) -> Option<IpAddr> {
+        let remote = normalize_ip(req.remote()?.ip());
+
+        if !self.is_trusted(&remote) {
+            return Some(remote);
+        }
+
+        let client =
+            self.client_ip_headers.iter().find_map(|header| {
+                if header.eq_ignore_ascii_case(FORWARDED_FOR) {
+                    self.forwarded_ip(req, remote)
+                } else {
+                    let value = req.headers().get_one(header)?;
+                    parse_forwarded_ip(value.trim()).map(normalize_ip)
+                }
+            });
+
+        Some(client.unwrap_or(remote))
+    }
+
+    /// Walks the `X-Forwarded-For` chain, returns `None` if there's
+    /// none.
+    fn forwarded_ip(
+        &self,
+        req: &Request<'_>,
+        remote: IpAddr,
+    ) -> Option<IpAddr> {
+        let hops: Vec<&str> = req
+            .headers()
+            .get(FORWARDED_FOR)
+            .flat_map(|value| value.split(','))
+            .map(str::trim)
+            .collect();
+
+        if hops.is_empty() {
+            return None;
+        }
+
+        let mut client = remote;
+
+        for hop in hops.into_iter().rev().take(self.depth) {
+            // Stop at malformed entries, everything to the left of
+            // them can't be trusted anyway.
+            let Some(ip) = parse_forwarded_ip(hop) else {
+                break;
+            };
+
+            client = normalize_ip(ip);
+
+            if !self.is_trusted(&client) {
+                break;
+            }
+        }
+
+        Some(client)
+    }
+}
+
+#[rocket::async_trait]
+impl KeyFilter<String> for ForwardedIpKeyFilter {
+    async fn key(&self, req: &Request<'_>) -> Option<String> {
+        self.client_ip(req).map(|ip| ip.to_string())
+    }
+}
+
+/// Parses an `X-Forwarded-For` entry, which may include a port.
+fn parse_forwarded_ip(hop: &str) -> Option<IpAddr> {
+    hop.parse::<IpAddr>()
+        .or_else(|_| hop.parse::<SocketAddr>().map(|addr| addr.ip()))
+        .ok()
+}
+
+/// Uses the client's IP subnet as the key.
+///
+/// IPv6 clients usually have a whole `/64` (or more) to rotate
+/// through, so keying on the exact address is easily evaded. This
+/// filter masks the address down to a prefix first, which defaults
+/// to `/64` for IPv6 and `/32` for IPv4:
+///
+/// ```no_run
+///# use rocket_rate_limit::IpSubnetKeyFilter;
+/// let filter = IpSubnetKeyFilter::new().v4_prefix(24).v6_prefix(56);
+/// ```
+///
+/// IPv4-mapped IPv6 addresses (`::ffff:1.2.3.4`) are treated as
+/// IPv4 addresses.
+///
+pub struct IpSubnetKeyFilter {
+    v4_prefix: u8,
+    v6_prefix: u8,
+}
+
+impl IpSubnetKeyFilter {
+    pub fn new() -> Self {
+        IpSubnetKeyFilter {
+            v4_prefix: 32,
+            v6_prefix: 64,
+        }
+    }
+
+    /// Sets the prefix length for IPv4 addresses.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `prefix` is greater than 32.
+    ///
+    pub fn v4_prefix(mut self, prefix: u8) -> Self {
+        assert!(
+            prefix <= 32,
+            "invalid IPv4 prefix length {}",
+            prefix
+        );
+
+        self.v4_prefix = prefix;
+        self
+    }
+
+    /// Sets the prefix length for IPv6 addresses.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `prefix` is greater than 128.
+    ///
+    pub fn v6_prefix(mut self, prefix: u8) -> Self {
+        assert!(
+            prefix <= 128,
+            "invalid IPv6 prefix length {}",
+            prefix
+        );
+
+        self.v6_prefix = prefix;
+        self
+    }
+
+    fn subnet(&self, ip: IpAddr) -> IpNet {
+        let ip = normalize_ip(ip);
+        let prefix = match ip {
+            IpAddr::V4(_) => self.v4_prefix,
+            IpAddr::V6(_) => self.v6_prefix,
+        };
+
+        IpNet::new(ip, prefix)
+            .expect("prefix length is validated")
+            .trunc()
+    }
+}
+
+impl Default for IpSubnetKeyFilter {
+    fn default() -> Self {
+        IpSubnetKeyFilter::new()
+    }
+}
+
+#[rocket::async_trait]
+impl KeyFilter<String> for IpSubnetKeyFilter {
+    async fn key(&self, req: &Request<'_>) -> Option<String> {
+        req.client_ip().map(|ip| self.subnet(ip).to_string())
+    }
+}
+
+/// Converts IPv4-mapped IPv6 addresses to IPv4.
+fn normalize_ip(ip: IpAddr) -> IpAddr {
+    match ip {
+        IpAddr::V6(v6) => match v6.to_ipv4_mapped() {
+            Some(v4) => IpAddr::V4(v4),
+            None => IpAddr::V6(v6),
+        },
+        ip => ip,
+    }
+}
+
+/// Uses a closure to extract the key.
+///
+/// This avoids declaring a struct for simple filters:
+///
+/// ```no_run
+///# use rocket_rate_limit::FnKeyFilter;
+/// let filter = FnKeyFilter::new(|req| {
+///     req.uri().path().segments().next().map(|s| s.to_string())
+/// });
+/// ```
+///
+/// See [AsyncFnKeyFilter] for filters that need to `.await`.
+///
+pub struct FnKeyFilter<F>(F);
+
+impl<F> FnKeyFilter<F> {
+    pub fn new<K>(f: F) -> Self
+    where
+        F: Fn(&Request<'_>) -> Option<K> + Send + Sync + 'static,
+    {
+        FnKeyFilter(f)
+    }
+}
+
+#[rocket::async_trait]
+impl<F, K> KeyFilter<K> for FnKeyFilter<F>
+where
+    F: Fn(&Request<'_>) -> Option<K> + Send + Sync + 'static,
+{
+    async fn key(&self, req: &Request<'_>) -> Option<K> {
+        (self.0)(req)
+    }
+}
+
+/// Uses an async closure to extract the key.
+///
+/// The closure has to box its future:
+///
+/// ```no_run
+///# use rocket_rate_limit::AsyncFnKeyFilter;
+/// let filter = AsyncFnKeyFilter::new(|req| {
+///     Box::pin(async move {
+///         req.headers().get_one("x-api-key").map(|key| key.to_string())
+///     })
+/// });
+/// ```
+///
+pub struct AsyncFnKeyFilter<F>(F);
+
+impl<F> AsyncFnKeyFilter<F> {
+    pub fn new<K>(f: F) -> Self
+    where
+        F: for<'a> Fn(&'a Request<'_>) -> BoxFuture<'a, Option<K>>
+            + Send
+            + Sync
+            + 'static,
+    {
+        AsyncFnKeyFilter(f)
+    }
+}
+
+#[rocket::async_trait]
+impl<F, K> KeyFilter<K> for AsyncFnKeyFilter<F>
+where
+    F: for<'a> Fn(&'a Request<'_>) -> BoxFuture<'a, Option<K>>
+        + Send
+        + Sync
+        + 'static,
+{
+    async fn key(&self, req: &Request<'_>) -> Option<K> {
+        (self.0)(req).await
+    }
+}
@@ -0,0 +1,122 @@
+use crate::{AsyncKeyedStore, RateLimitStatus, StoreOutcome};
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::num::NonZeroU32;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// A store that allows `limit` requests per wall-clock window, e.g.
+/// per UTC hour.
+///
+/// Windows start at multiples of their length since the Unix epoch,
+/// so a window of an hour starts on the hour, and all counters are
+/// reset when the next one starts. Use it with
+/// [RateLimitConfig::fixed_window]:
+///
+/// ```no_run
+///# use rocket_rate_limit::{IpKeyFilter, RateLimitConfig};
+///# use governor::state::keyed::DefaultKeyedStateStore;
+///# use std::num::NonZeroU32;
+///# use std::time::Duration;
+/// let config: RateLimitConfig<String, DefaultKeyedStateStore<String>> =
+///     RateLimitConfig::fixed_window(
+///         NonZeroU32::new(1000).unwrap(),
+///         Duration::from_secs(60 * 60),
+///         Box::new(IpKeyFilter),
+///     );
+/// ```
+///
+/// Unlike governor's quotas, a client can use its whole limit at the
+/// end of one window and again at the start of the next. Windows
+/// follow the system time, not the clock of the rate limiter.
+///
+/// [RateLimitConfig::fixed_window]: crate::RateLimitConfig::fixed_window
+///
+pub struct FixedWindow<K> {
+    limit: NonZeroU32,
+    window: Duration,
+    counters: Mutex<Counters<K>>,
+}
+
+struct Counters<K> {
+    /// Index of the window the counts belong to.
+    window: u128,
+    counts: HashMap<K, u32>,
+}
+
+impl<K: Eq + Hash + Clone> FixedWindow<K> {
+    /// # Panics
+    ///
+    /// Panics if `window` is zero.
+    ///
+    pub fn new(limit: NonZeroU32, window: Duration) -> Self {
+        assert!(!window.is_zero(), "the window must not be zero");
+
+        FixedWindow {
+            limit,
+            window,
+            counters: Mutex::new(Counters {
+                window: 0,
+                counts: HashMap::new(),
+            }),
+        }
+    }
+
+    fn status(
+        &self,
+        remaining: u32,
+        reset: Duration,
+    ) -> RateLimitStatus {
+        RateLimitStatus::new(
+            self.limit.get(),
+            remaining,
+            reset,
+            self.window,
+        )
+    }
+}
+
+#[rocket::async_trait]
+impl<K> AsyncKeyedStore<K> for FixedWindow<K>
+where
+    K: Eq + Hash + Clone + Send + Sync,
+{
+    async fn check(&self, key: &K, cost: NonZeroU32) -> StoreOutcome {
+        if cost > self.limit {
+            return StoreOutcome::ExceedsBurst {
+                burst_size: self.limit.get(),
+            };
+        }
+
+        let since_epoch = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos();
+        let length = self.window.as_nanos();
+        let window = since_epoch / length;
+        // Time until the next window starts.
+        let reset = Duration::from_nanos(
+            ((window + 1) * length - since_epoch) as u64,
+        );
+
+        let mut counters = self.counters.lock().unwrap();
+
+        if counters.window != window {
+            counters.window = window;
+            counters.counts.clear();
+        }
+
+        let count = counters.counts.entry(key.clone()).or_default();
+        let limit = self.limit.get();
+
+        if *count + cost.get() > limit {
+            return StoreOutcome::Limited(
+                self.status(limit - *count, reset),
+            );
+        }
+
+        *count += cost.get();
+
+        StoreOutcome::Allowed(self.status(limit - *count, reset))
+    }
+}
@@ -1,49 +1,211 @@
 pub extern crate governor;
+pub extern crate ipnet;
 
-use governor::clock::{Clock, DefaultClock};
-use governor::state::keyed::KeyedStateStore;
-use governor::RateLimiter;
-use rocket::fairing::{Fairing, Info, Kind};
+use ban::BanPolicy;
+use concurrency::{Acquired, Permits};
+use governor::clock::{Clock, DefaultClock, Reference};
+use governor::middleware::{
+    StateInformationMiddleware, StateSnapshot,
+};
+use governor::nanos::Nanos;
+use governor::state::direct::NotKeyed;
+use governor::state::keyed::{
+    KeyedStateStore, ShrinkableKeyedStateStore,
+};
+use governor::state::InMemoryState;
+use governor::{
+    InsufficientCapacity, Jitter, NotUntil, Quota, RateLimiter,
+};
+use ipnet::IpNet;
+use rocket::fairing::{self, Fairing, Info, Kind};
+use rocket::futures::future::BoxFuture;
 use rocket::http::uri::Origin;
-use rocket::http::{Header, Status};
-use rocket::{Data, Request, Response, Route};
-use std::collections::HashMap;
+use rocket::http::{ContentType, Header, Method, Status};
+use rocket::outcome::Outcome;
+use rocket::request::{self, FromRequest};
+use rocket::route;
+use rocket::{Build, Data, Orbit, Request, Response, Rocket, Route};
+use route_index::RouteIndex;
+use std::cell::Cell;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::convert::Infallible;
+use std::error::Error;
+use std::fmt;
 use std::hash::Hash;
+use std::net::IpAddr;
+use std::num::NonZeroU32;
+use std::sync::atomic::{
+    AtomicBool, AtomicU64, AtomicUsize, Ordering,
+};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, SystemTime};
+use tiered::TieredLimiter;
+
+mod async_store;
+mod ban;
+mod builder;
+mod concurrency;
+mod config;
+mod filters;
+mod fixed_window;
+#[cfg(feature = "macros")]
+pub mod macros;
+mod persistent_store;
+mod quota_grammar;
+#[cfg(feature = "redis")]
+mod redis_store;
+mod route_index;
+mod shared_store;
+mod sliding_window;
+#[cfg(feature = "testing")]
+pub mod testing;
+mod tiered;
+
+pub use async_store::{AsyncKeyedStore, StoreOutcome};
+pub use builder::{RateLimitBuilder, RouteBuilder};
+pub use concurrency::ConcurrencyLimit;
+pub use config::{InvalidQuota, QuotaSpec};
+pub use filters::{
+    AndFilter, AsyncFnKeyFilter, AuthBearerKeyFilter,
+    ConstantKeyFilter, CookieKeyFilter, ExceptCidr, ExceptFilter,
+    FallbackIpKeyFilter, FnKeyFilter, ForwardedIpKeyFilter,
+    HashedFilter, HeaderKeyFilter, HostKeyFilter, IpAddrKeyFilter,
+    IpKeyFilter, IpSubnetKeyFilter, MissingIpPolicy, OrFilter,
+    OriginKeyFilter, PathSegmentKeyFilter, QueryKeyFilter, UserId,
+    UserIdKeyFilter, WithMethod,
+};
+pub use fixed_window::FixedWindow;
+pub use persistent_store::{PersistentStore, StoreSnapshot};
+#[cfg(feature = "redis")]
+pub use redis_store::RedisKeyedStore;
+pub use shared_store::SharedStore;
+pub use sliding_window::SlidingWindowLog;
+pub use tiered::{QuotaProvider, QuotaTable};
 
 #[macro_export]
 macro_rules! rate_limit {
   {
     $(
         $name:literal => [
-          $( { quota: $quota:expr, filter: $filter:ident } ), +
+          $( {
+            quota: $quota:expr,
+            filter: $filter:expr
+            $(, priority: $priority:expr)?
+          } ), +
         ]
     ), +
   } => {
-    let mut rate_limit = ::rocket_rate_limit::RateLimit::default();
-
-    $(
-      rate_limit.add($name, vec![
+    ::rocket_rate_limit::RateLimit::builder()
+      $(
+        .route($name)
         $(
-          ::rocket_rate_limit::RateLimitConfig::new(
-            ::rocket_rate_limit::governor::RateLimiter::keyed($quota),
-            Box::new($filter)
+          .config(
+            ::rocket_rate_limit::RateLimitConfig::keyed(
+              $quota,
+              Box::new($filter)
+            )
+            $(.priority($priority))?
           )
         )+
-      ]);
-    )+
-
-    rate_limit
+        .finish()
+      )+
+      .build()
   }
 }
 
-/// URI that rate limited requests get redirected to.
+/// Default URI that rate limited requests get redirected to, see
+/// [RateLimit::internal_path].
 ///
 /// This is a magic value which allows the rate limiter to work.
-/// Please don't use the same path in your routes.
+/// The fairing mounts its own routes at this path, so please don't
+/// use the same path in your routes.
+///
+/// The routes are mounted below every base routes are mounted at,
+/// and requests are redirected below the base of their route, so
+/// catchers registered for that base still apply.
 ///
 const DUMMY_HANDLER_URI: &'static str =
     "/rate-limiter-handler-ZoIGMRpd2xPAOawvWc2T8m9Hs33E3kX8";
 
+/// Name of the dummy routes, which tells them apart from user routes
+/// at the same path.
+const DUMMY_ROUTE_NAME: &str = "rocket_rate_limit_internal";
+
+/// Path of the dummy routes mounted at `base`.
+fn dummy_path(base: &str, internal_path: &str) -> String {
+    format!("{}{}", base.trim_end_matches('/'), internal_path)
+}
+
+/// Routes that handle the redirected requests for every method.
+///
+/// The actual response is built in the fairing's `on_response`,
+/// unless the rate limiter that redirected the request lets
+/// catchers render it. The routes have the lowest possible rank, so
+/// they always win over user routes matching the same path.
+///
+fn dummy_routes(internal_path: &str) -> Vec<Route> {
+    [
+        Method::Get,
+        Method::Put,
+        Method::Post,
+        Method::Delete,
+        Method::Options,
+        Method::Head,
+        Method::Patch,
+    ]
+    .into_iter()
+    .map(|method| {
+        let mut route = Route::ranked(
+            isize::MIN,
+            method,
+            internal_path,
+            DummyHandler,
+        );
+        route.name = Some(DUMMY_ROUTE_NAME.into());
+        route
+    })
+    .collect()
+}
+
+#[derive(Clone)]
+struct DummyHandler;
+
+#[rocket::async_trait]
+impl route::Handler for DummyHandler {
+    async fn handle<'r>(
+        &self,
+        req: &'r Request<'_>,
+        _: Data<'r>,
+    ) -> route::Outcome<'r> {
+        match req.local_cache(|| CatcherStatus(None)).0 {
+            Some(status) => route::Outcome::Failure(status),
+            None => route::Outcome::from(req, ()),
+        }
+    }
+}
+
+/// The URI of a request before it was redirected to the dummy
+/// handler.
+struct OriginalUri(Option<Origin<'static>>);
+
+/// Status the dummy handler fails with, so a catcher renders the
+/// rejection.
+struct CatcherStatus(Option<Status>);
+
+/// Returns the URI of a rate limited request before it was
+/// redirected.
+///
+/// Rate limited requests are redirected to an internal route, which
+/// replaces their path (the query is kept). Fairings and catchers
+/// that run afterwards can use this to get the original URI.
+/// Returns `None` if the request wasn't rate limited.
+///
+pub fn original_uri<'a>(
+    req: &'a Request<'_>,
+) -> Option<&'a Origin<'static>> {
+    req.local_cache(|| OriginalUri(None)).0.as_ref()
+}
+
 /// Dynamically extract rate-limit keys from requests.
 ///
 /// This allows for custom key implementations. For example:
@@ -68,6 +230,10 @@ const DUMMY_HANDLER_URI: &'static str =
 /// }
 /// ```
 ///
+/// Filters only get the request, never its body, so rate limiting
+/// can't consume the data a handler reads. Keys or costs that depend
+/// on the size of the body can use the `Content-Length` header.
+///
 #[rocket::async_trait]
 pub trait KeyFilter<K> {
     /// Extracts a key for the rate limiter.
@@ -77,195 +243,3293 @@ pub trait KeyFilter<K> {
     async fn key(&self, req: &Request<'_>) -> Option<K>;
 }
 
-pub struct IpKeyFilter;
+/// Returns state managed by Rocket, for filters that need e.g. a
+/// database pool:
+///
+/// ```no_run
+///# use rocket::Request;
+///# use rocket_rate_limit::{managed_state, KeyFilter};
+///# struct Sessions;
+///# impl Sessions {
+///#     async fn user_id(&self, token: &str) -> Option<String> { None }
+///# }
+/// struct SessionFilter;
+///
+/// #[rocket::async_trait]
+/// impl KeyFilter<String> for SessionFilter {
+///     async fn key(&self, req: &Request<'_>) -> Option<String> {
+///         let sessions = managed_state::<Sessions>(req)?;
+///         let token = req.headers().get_one("authorization")?;
+///
+///         sessions.user_id(token).await
+///     }
+/// }
+/// ```
+///
+/// Returns `None` if no state of type `T` is managed, which skips
+/// the config.
+///
+pub fn managed_state<'r, T>(req: &'r Request<'_>) -> Option<&'r T>
+where
+    T: Send + Sync + 'static,
+{
+    req.rocket().state::<T>()
+}
 
+/// Like [KeyFilter], but extracting the key can fail.
+///
+/// Use it with [RateLimitConfig::try_new]. Whether failures skip the
+/// config or reject the request is up to
+/// [RateLimitConfig::fail_closed], unless the filter fails with
+/// [KeyError::reject].
+///
 #[rocket::async_trait]
-impl KeyFilter<String> for IpKeyFilter {
-    async fn key(&self, req: &Request<'_>) -> Option<String> {
-        req.client_ip().map(|ip| ip.to_string())
-    }
+pub trait TryKeyFilter<K> {
+    /// Extracts a key for the rate limiter.
+    ///
+    /// If `Ok(None)` is returned, the [RateLimitConfig] is skipped.
+    ///
+    async fn try_key(
+        &self,
+        req: &Request<'_>,
+    ) -> Result<Option<K>, KeyError>;
 }
 
-#[derive(Default)]
-pub struct RateLimit<K, S>
-where
-    K: Eq + Clone + Hash,
-    S: KeyedStateStore<K>,
-{
-    configs: HashMap<String, Vec<RateLimitConfig<K, S>>>,
-    clock: DefaultClock,
+/// Error of a [TryKeyFilter].
+#[derive(Debug)]
+pub struct KeyError {
+    error: Box<dyn Error + Send + Sync>,
+    reject: bool,
 }
 
-impl<K, S> RateLimit<K, S>
-where
-    K: Eq + Clone + Hash,
-    S: KeyedStateStore<K>,
-{
-    pub fn new(
-        configs: HashMap<String, Vec<RateLimitConfig<K, S>>>,
-    ) -> Self {
-        RateLimit {
-            configs,
-            clock: DefaultClock::default(),
+impl KeyError {
+    pub fn new<E>(error: E) -> Self
+    where
+        E: Into<Box<dyn Error + Send + Sync>>,
+    {
+        KeyError {
+            error: error.into(),
+            reject: false,
         }
     }
 
-    pub fn add<R, I>(&mut self, route_name: R, items_iter: I)
+    /// Creates an error that rejects the request, even if the config
+    /// isn't [fail_closed](RateLimitConfig::fail_closed).
+    ///
+    /// Useful for requests the filter deliberately refuses, rather
+    /// than failures of e.g. a database.
+    ///
+    pub fn reject<E>(error: E) -> Self
     where
-        R: AsRef<str>,
-        I: IntoIterator<Item = RateLimitConfig<K, S>>,
+        E: Into<Box<dyn Error + Send + Sync>>,
     {
-        let route_name = route_name.as_ref();
+        KeyError {
+            error: error.into(),
+            reject: true,
+        }
+    }
 
-        if let Some(ref mut items) = self.configs.get_mut(route_name)
-        {
-            items.extend(items_iter);
+    /// Whether the error rejects the request regardless of
+    /// [RateLimitConfig::fail_closed], see [KeyError::reject].
+    pub fn rejects(&self) -> bool {
+        self.reject
+    }
+}
 
-            // Sort in reverse order by priority.
-            items.sort_by(|a, b| b.priority.cmp(&a.priority));
-        } else {
-            self.configs.insert(
-                route_name.to_string(),
-                items_iter.into_iter().collect(),
-            );
-        }
+impl fmt::Display for KeyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.error.fmt(f)
     }
+}
 
-    async fn check_rate_limit(
+impl Error for KeyError {}
+
+/// Adapts a [KeyFilter] to a [TryKeyFilter] that never fails.
+struct InfallibleFilter<K>(Box<dyn KeyFilter<K> + Send + Sync>);
+
+#[rocket::async_trait]
+impl<K> TryKeyFilter<K> for InfallibleFilter<K> {
+    async fn try_key(
         &self,
         req: &Request<'_>,
-        route: Option<&Route>,
-    ) -> RateLimitResult {
-        let configs = route
-            .and_then(|route| route.name.as_ref())
-            .and_then(|name| self.configs.get(name.as_ref()))?;
-
-        // Check if the context matches the mode.
-        for cfg in configs {
-            let result =
-                cfg.filter.key(req).await.and_then(|key| {
-                    cfg.limiter.check_key(&key).err()
-                });
-
-            if let Some(err_outcome) = result {
-                return Some(RateLimitResponse {
-                    retry_after: err_outcome
-                        .wait_time_from(self.clock.now())
-                        .as_millis(),
-                });
-            }
-        }
+    ) -> Result<Option<K>, KeyError> {
+        Ok(self.0.key(req).await)
+    }
+}
 
-        None
+pub struct RateLimit<K, S, C = DefaultClock>
+where
+    K: Eq + Clone + Hash,
+    S: KeyedStateStore<K>,
+    C: Clock,
+{
+    configs: HashMap<String, Vec<Arc<RateLimitConfig<K, S, C>>>>,
+    clock: C,
+    headers: bool,
+    header_style: HeaderStyle,
+    retry_after_format: RetryAfterFormat,
+    body: RejectBody,
+    reject_content_type: Option<ContentType>,
+    reject_status: Status,
+    allowlist: HashSet<K>,
+    allowed_networks: Vec<IpNet>,
+    /// Converts keys to IP addresses for [RateLimit::allow_network].
+    key_ip: Option<fn(&K) -> IpAddr>,
+    /// The sweep returns whether the limiter is left without keys.
+    gc: Option<(Duration, fn(&Limiter<K, S, C>) -> bool)>,
+    on_limited: Option<LimitedFn>,
+    redirect: bool,
+    global: Vec<Arc<RateLimitConfig<K, S, C>>>,
+    defaults: Vec<Arc<RateLimitConfig<K, S, C>>>,
+    mode: RateLimitMode,
+    concurrency: HashMap<String, Vec<ConcurrencyLimit<K>>>,
+    ban: Option<BanPolicy<K, C::Instant>>,
+    overwrite_headers: bool,
+    retry_jitter: Option<Jitter>,
+    retry_after_bounds: Option<(Duration, Duration)>,
+    catchers: bool,
+    strict_route_names: bool,
+    internal_path: String,
+    on_store_error: StoreErrorPolicy,
+    namespace: Option<NamespaceFn<K>>,
+    allowed: AtomicU64,
+    denied: AtomicU64,
+    enabled: AtomicBool,
+    /// Tells the request-local results of several attached rate
+    /// limiters apart.
+    id: usize,
+    /// The routes of the application, captured on liftoff.
+    routes: OnceLock<RouteIndex>,
+}
+
+impl<K, S, C> RateLimit<K, S, C>
+where
+    K: Eq + Clone + Hash,
+    S: ShrinkableKeyedStateStore<K>,
+    C: Clock,
+{
+    /// Periodically removes keys that no longer affect rate limits,
+    /// which bounds the memory used by the store.
+    ///
+    /// Only available for stores which can be shrunk, like the
+    /// default in-memory stores. Without calling this, stale keys
+    /// are never removed. Ten minutes is a reasonable interval for
+    /// most applications, see [DEFAULT_GC_INTERVAL]. A zero interval
+    /// disables the sweep.
+    ///
+    /// The limiters of [tiered](RateLimitConfig::tiered) configs are
+    /// dropped once they have no keys left, and created again when
+    /// their quota is chosen.
+    ///
+    pub fn gc_interval(mut self, interval: Duration) -> Self {
+        let sweep: fn(&Limiter<K, S, C>) -> bool = |limiter| {
+            limiter.retain_recent();
+            limiter.shrink_to_fit();
+            limiter.is_empty()
+        };
+
+        self.gc = if interval.is_zero() {
+            None
+        } else {
+            Some((interval, sweep))
+        };
+        self
     }
 
-    fn apply_rate_limit(
-        &self,
-        res: &mut Response<'_>,
-        rate_limit: &RateLimitResponse,
-    ) {
-        use std::io::Cursor;
+    /// Number of keys each config of a route currently holds, in the
+    /// order the configs are checked.
+    ///
+    /// Single bucket limits count as one key, and configs with an
+    /// [AsyncKeyedStore] as none, since their keys aren't kept in
+    /// memory. Concurrent stores may report an approximate count.
+    /// Returns an empty list if the route has no configs.
+    ///
+    pub fn key_count(&self, route_name: &str) -> Vec<usize> {
+        let configs = match lookup_route(&self.configs, route_name) {
+            Some(configs) => configs,
+            None => return Vec::new(),
+        };
 
-        res.set_status(Status::TooManyRequests);
+        configs
+            .iter()
+            .map(|cfg| match &cfg.limiter {
+                ConfigLimiter::Keyed(limiter) => limiter.len(),
+                ConfigLimiter::Direct(_) => 1,
+                ConfigLimiter::Async(_) => 0,
+                ConfigLimiter::Tiered(tiers) => tiers
+                    .limiters()
+                    .iter()
+                    .map(|limiter| limiter.len())
+                    .sum(),
+                ConfigLimiter::Layered(limiters) => {
+                    limiters.iter().map(|limiter| limiter.len()).sum()
+                }
+            })
+            .collect()
+    }
+}
 
-        // Add rate-limit headers.
-        res.set_header(Header::new(
-            "Retry-After",
-            rate_limit.retry_after.to_string(),
-        ));
+impl<S, C> RateLimit<String, S, C>
+where
+    S: KeyedStateStore<String>,
+    C: Clock,
+{
+    /// Prefixes every key with `namespace`, e.g. `prod:1.2.3.4`, so
+    /// environments that share a store don't share their limits.
+    ///
+    /// Filters, the [allowlist](RateLimit::allow) and
+    /// [reset](RateLimit::reset) keep using keys without the prefix,
+    /// the prefix is added before they reach a store or a ban.
+    /// Concurrency limits are kept per process, so they aren't
+    /// prefixed.
+    ///
+    pub fn namespace<N: Into<String>>(
+        mut self,
+        namespace: N,
+    ) -> Self {
+        let namespace = namespace.into();
 
-        // Remove the body (set empty body with 0 length).
-        res.set_sized_body(0, Cursor::new(String::new()));
+        self.namespace = Some(Box::new(move |key| {
+            format!("{}:{}", namespace, key)
+        }));
+        self
     }
 }
 
-#[derive(Clone, Copy)]
-struct RateLimitResponse {
-    retry_after: u128,
+impl<S, C> RateLimit<IpAddr, S, C>
+where
+    S: KeyedStateStore<IpAddr>,
+    C: Clock,
+{
+    /// Exempts every IP address in `network` from rate limiting,
+    /// e.g. an office subnet.
+    ///
+    /// Like [allow](RateLimit::allow) for whole networks, for rate
+    /// limiters keyed by `IpAddr`, e.g. with [IpAddrKeyFilter].
+    ///
+    pub fn allow_network(&mut self, network: IpNet) {
+        self.key_ip = Some(|ip| *ip);
+        self.allowed_networks.push(network);
+    }
 }
 
-type RateLimitResult = Option<RateLimitResponse>;
+/// Suggested interval for [RateLimit::gc_interval].
+pub const DEFAULT_GC_INTERVAL: Duration =
+    Duration::from_secs(10 * 60);
 
-pub struct RateLimitConfig<K, S>
+impl<K, S, C> Default for RateLimit<K, S, C>
 where
     K: Eq + Clone + Hash,
     S: KeyedStateStore<K>,
+    C: Clock + Default,
 {
-    limiter: RateLimiter<K, S, DefaultClock>,
-    filter: Box<dyn KeyFilter<K> + Send + Sync>,
-    priority: u32,
+    fn default() -> Self {
+        RateLimit::new(HashMap::new())
+    }
 }
 
-impl<K, S> RateLimitConfig<K, S>
+/// Renders the body of a rejected request.
+type BodyFn = Box<
+    dyn Fn(&RateLimitResponse) -> (ContentType, String) + Send + Sync,
+>;
+
+/// Body of rejected responses.
+enum RejectBody {
+    Empty,
+    Custom(BodyFn),
+    #[cfg(feature = "json")]
+    ProblemJson,
+}
+
+/// Called when a request is rate limited.
+type LimitedFn =
+    Box<dyn Fn(&Request<'_>, &RateLimitResponse) + Send + Sync>;
+
+/// Format of the quota headers attached by [RateLimit::headers].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum HeaderStyle {
+    /// `X-RateLimit-Limit`, `X-RateLimit-Remaining` and
+    /// `X-RateLimit-Reset` headers.
+    #[default]
+    Legacy,
+    /// `RateLimit` and `RateLimit-Policy` headers, as described in
+    /// the IETF `draft-ietf-httpapi-ratelimit-headers` draft.
+    IetfDraft,
+}
+
+/// What to do with requests whose store can't be reached, see
+/// [RateLimit::on_store_error].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum StoreErrorPolicy {
+    /// Let the request through, as if it wasn't rate limited.
+    #[default]
+    FailOpen,
+    /// Reject the request with `503 Service Unavailable`.
+    FailClosed,
+}
+
+/// Format of the `Retry-After` header.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum RetryAfterFormat {
+    /// Seconds to wait, e.g. `Retry-After: 5`.
+    #[default]
+    Seconds,
+    /// The time to retry at, as an HTTP date, e.g.
+    /// `Retry-After: Wed, 21 Oct 2015 07:28:00 GMT`.
+    HttpDate,
+}
+
+/// What happens to requests that exceed their quota.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum RateLimitMode {
+    /// Reject the request.
+    #[default]
+    Reject,
+    /// Wait until the quota allows the request, then let it proceed.
+    ///
+    /// Requests that would have to wait longer than `max_delay` in
+    /// total are rejected. Every retry is checked against all
+    /// configs of the route again, so configs which allowed the
+    /// first attempt are charged again.
+    ///
+    Delay { max_delay: Duration },
+}
+
+impl<K, S, C> RateLimit<K, S, C>
 where
     K: Eq + Clone + Hash,
     S: KeyedStateStore<K>,
+    C: Clock + Default,
 {
     pub fn new(
-        limiter: RateLimiter<K, S, DefaultClock>,
-        filter: Box<dyn KeyFilter<K> + Send + Sync>,
+        configs: HashMap<String, Vec<RateLimitConfig<K, S, C>>>,
     ) -> Self {
-        RateLimitConfig {
-            limiter,
-            filter,
-            priority: 0,
-        }
+        RateLimit::with_clock(configs, C::default())
     }
 
-    pub fn priority(mut self, priority: u32) -> Self {
-        self.priority = priority;
-        self
+    /// Starts building a rate limiter route by route, see
+    /// [RateLimitBuilder].
+    ///
+    pub fn builder() -> RateLimitBuilder<K, S, C> {
+        RateLimitBuilder::new(RateLimit::default())
     }
 }
 
-#[rocket::async_trait]
-impl<K, S> Fairing for RateLimit<K, S>
+impl<K, S, C> RateLimit<K, S, C>
 where
-    S: KeyedStateStore<K> + Send + Sync + 'static,
-    K: Eq + Clone + Hash + Send + Sync + 'static,
+    K: Eq + Clone + Hash,
+    S: KeyedStateStore<K>,
+    C: Clock,
 {
-    fn info(&self) -> Info {
-        Info {
-            name: "Rate Limit",
-            kind: Kind::Request | Kind::Response,
+    /// Creates a rate limiter that uses a custom clock.
+    ///
+    /// The clock has to be the same one the limiters of the configs
+    /// use, e.g. a `QuantaClock` for a higher resolution, or a
+    /// `FakeRelativeClock` in tests:
+    ///
+    /// ```no_run
+    ///# use governor::clock::FakeRelativeClock;
+    ///# use governor::state::keyed::DefaultKeyedStateStore;
+    ///# use governor::{Quota, RateLimiter};
+    ///# use rocket_rate_limit::{IpKeyFilter, RateLimit, RateLimitConfig};
+    ///# use std::collections::HashMap;
+    ///# use std::num::NonZeroU32;
+    ///# use std::time::Duration;
+    /// let clock = FakeRelativeClock::default();
+    ///
+    /// let mut rate_limit = RateLimit::with_clock(HashMap::new(), clock.clone());
+    /// rate_limit.add("route_name", vec![RateLimitConfig::new(
+    ///     RateLimiter::new(
+    ///         Quota::per_second(NonZeroU32::new(1).unwrap()),
+    ///         DefaultKeyedStateStore::<String>::default(),
+    ///         &clock,
+    ///     ),
+    ///     Box::new(IpKeyFilter),
+    /// )]);
+    ///
+    /// // Refill the bucket.
+    /// clock.advance(Duration::from_secs(1));
+    /// ```
+    ///
+    pub fn with_clock(
+        configs: HashMap<String, Vec<RateLimitConfig<K, S, C>>>,
+        clock: C,
+    ) -> Self {
+        let configs = configs
+            .into_iter()
+            .map(|(name, items)| {
+                let mut items: Vec<_> =
+                    items.into_iter().map(Arc::new).collect();
+
+                sort_by_priority(&mut items);
+                (name, items)
+            })
+            .collect();
+
+        RateLimit {
+            configs,
+            clock,
+            headers: false,
+            header_style: HeaderStyle::default(),
+            retry_after_format: RetryAfterFormat::default(),
+            body: RejectBody::Empty,
+            reject_content_type: None,
+            reject_status: Status::TooManyRequests,
+            allowlist: HashSet::new(),
+            allowed_networks: Vec::new(),
+            key_ip: None,
+            gc: None,
+            on_limited: None,
+            redirect: true,
+            global: Vec::new(),
+            defaults: Vec::new(),
+            mode: RateLimitMode::default(),
+            concurrency: HashMap::new(),
+            ban: None,
+            overwrite_headers: true,
+            retry_jitter: None,
+            retry_after_bounds: None,
+            catchers: false,
+            strict_route_names: false,
+            internal_path: DUMMY_HANDLER_URI.to_string(),
+            on_store_error: StoreErrorPolicy::default(),
+            namespace: None,
+            allowed: AtomicU64::new(0),
+            denied: AtomicU64::new(0),
+            enabled: AtomicBool::new(true),
+            id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
+            routes: OnceLock::new(),
         }
     }
 
-    async fn on_request(
-        &self,
-        req: &mut Request<'_>,
-        _data: &mut Data<'_>,
-    ) {
-        let route =
-            req.rocket().routes().find(|route| route.matches(req));
+    /// Attaches `X-RateLimit-Limit`, `X-RateLimit-Remaining` and
+    /// `X-RateLimit-Reset` headers to every response of a rate
+    /// limited route.
+    ///
+    /// Disabled by default.
+    ///
+    pub fn headers(mut self, enabled: bool) -> Self {
+        self.headers = enabled;
+        self
+    }
 
-        let result = self.check_rate_limit(req, route).await;
+    /// Selects the format of the quota headers.
+    ///
+    /// Defaults to [HeaderStyle::Legacy]. Only has an effect when
+    /// headers are enabled with [RateLimit::headers].
+    ///
+    pub fn header_style(mut self, style: HeaderStyle) -> Self {
+        self.header_style = style;
+        self
+    }
 
-        if let Some(rate_limit) = result {
-            let uri =
-                Origin::parse_owned(format!("{}", DUMMY_HANDLER_URI))
-                    .expect("valid redirect uri");
+    /// Selects the format of `Retry-After`.
+    ///
+    /// Defaults to [RetryAfterFormat::Seconds]. HTTP dates are
+    /// computed from the system time when the response is sent, while
+    /// the limits keep using the clock of the rate limiter.
+    ///
+    pub fn retry_after_format(
+        mut self,
+        format: RetryAfterFormat,
+    ) -> Self {
+        self.retry_after_format = format;
+        self
+    }
 
-            req.set_uri(uri);
+    /// Selects whether `Retry-After` and the quota headers replace
+    /// headers of the same name that the handler or another fairing
+    /// already set.
+    ///
+    /// Enabled by default. Disable it if e.g. a proxy sets an
+    /// authoritative `Retry-After`.
+    ///
+    pub fn overwrite_headers(mut self, enabled: bool) -> Self {
+        self.overwrite_headers = enabled;
+        self
+    }
 
-            req.local_cache(|| Some(rate_limit));
-        }
+    /// Adds a random delay to the `Retry-After` of rejections, so
+    /// clients that were rejected at the same time don't all retry
+    /// at once.
+    ///
+    /// The delay is only added to what's sent to the client, in
+    /// `Retry-After`, the reset of the quota headers and to
+    /// [with_body](RateLimit::with_body) alike, never subtracted.
+    /// Disabled by default.
+    ///
+    pub fn retry_jitter(mut self, jitter: Jitter) -> Self {
+        self.retry_jitter = Some(jitter);
+        self
     }
 
-    async fn on_response<'r>(
-        &self,
-        req: &'r Request<'_>,
-        res: &mut Response<'r>,
-    ) {
-        if req.uri().path() != DUMMY_HANDLER_URI {
-            return;
-        }
+    /// Clamps the `Retry-After` of rejections to `min..=max`, e.g.
+    /// because clients handle waits of several hours poorly.
+    ///
+    /// Only the wait sent to the client is clamped, including the
+    /// reset of the quota headers, the quota is enforced as before.
+    /// By default, the wait isn't clamped.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `min` is greater than `max`.
+    ///
+    pub fn retry_after_bounds(
+        mut self,
+        min: Duration,
+        max: Duration,
+    ) -> Self {
+        assert!(
+            min <= max,
+            "retry after bounds must not be reversed, got {:?}..={:?}",
+            min,
+            max
+        );
+
+        self.retry_after_bounds = Some((min, max));
+        self
+    }
+
+    /// Selects whether requests that exceed their quota are rejected
+    /// or delayed.
+    ///
+    /// Defaults to [RateLimitMode::Reject].
+    ///
+    pub fn mode(mut self, mode: RateLimitMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    /// Bans keys that are rate limited more than `violations` times
+    /// within `within`.
+    ///
+    /// Requests of a banned key are rejected for `ban_for` without
+    /// checking any quota, with the remaining ban time as
+    /// `Retry-After`. Direct limiters have no key, so they never
+    /// cause a ban.
+    ///
+    pub fn ban_policy(
+        mut self,
+        violations: u32,
+        within: Duration,
+        ban_for: Duration,
+    ) -> Self {
+        self.ban = Some(BanPolicy::new(violations, within, ban_for));
+        self
+    }
 
-        let dummy_result: RateLimitResult = None;
-        let result = req.local_cache(|| dummy_result);
+    /// Selects how rate limited requests are kept from reaching
+    /// their handler.
+    ///
+    /// By default, their URI is rewritten to point to an internal
+    /// route. When disabled, the URI is left untouched, and routes
+    /// have to use the [NotRateLimited] guard instead:
+    ///
+    /// ```no_run
+    ///# use rocket::get;
+    ///# use rocket_rate_limit::NotRateLimited;
+    /// #[get("/search")]
+    /// fn search(_guard: NotRateLimited) -> &'static str {
+    ///     "Only called if the request isn't rate limited."
+    /// }
+    /// ```
+    ///
+    /// Either way, the response is replaced with the rejection.
+    /// Without the guard, the handler of a rate limited request
+    /// still runs, though. Connection upgrades, e.g. WebSocket
+    /// handshakes, of rejected responses are cancelled.
+    ///
+    pub fn redirect(mut self, enabled: bool) -> Self {
+        self.redirect = enabled;
+        self
+    }
+
+    /// Fails ignition if several rate limited routes share a name.
+    ///
+    /// Configs are looked up by route name, so all routes with the
+    /// same name share their limits. Such routes are always logged
+    /// through Rocket's logger, and this turns the warning into an
+    /// error. Disabled by default.
+    ///
+    pub fn strict_route_names(mut self, enabled: bool) -> Self {
+        self.strict_route_names = enabled;
+        self
+    }
+
+    /// Decides what happens to requests whose store can't be reached,
+    /// e.g. while Redis is down.
+    ///
+    /// Defaults to [StoreErrorPolicy::FailOpen], which lets them
+    /// through unlimited. [StoreErrorPolicy::FailClosed] rejects them
+    /// with `503 Service Unavailable` instead, whatever the
+    /// [reject_status](RateLimit::reject_status). The error is logged
+    /// either way.
+    ///
+    /// Stores report errors with [StoreOutcome::Unavailable], or
+    /// [report_store_error] for governor's synchronous stores.
+    ///
+    pub fn on_store_error(
+        mut self,
+        policy: StoreErrorPolicy,
+    ) -> Self {
+        self.on_store_error = policy;
+        self
+    }
+
+    /// Sets the path rate limited requests are redirected to, see
+    /// [redirect](RateLimit::redirect).
+    ///
+    /// The fairing mounts its internal routes at this path below
+    /// every base, so it must not be used by routes of the
+    /// application. Ignition fails if a route ends with it. Defaults
+    /// to a long random path.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `path` isn't an absolute path of its own, e.g.
+    /// `/rate-limited`, without a query.
+    ///
+    pub fn internal_path(mut self, path: &str) -> Self {
+        assert!(
+            path.len() > 1
+                && path.starts_with('/')
+                && !path.ends_with('/')
+                && Origin::parse(path)
+                    .is_ok_and(|origin| origin.query().is_none()),
+            "invalid internal path {:?}",
+            path
+        );
+
+        self.internal_path = path.to_string();
+        self
+    }
+
+    /// Lets Rocket's catchers render rejected responses, e.g. a
+    /// `#[catch(429)]` handler.
+    ///
+    /// Redirected requests fail with the
+    /// [reject_status](RateLimit::reject_status) instead of getting a
+    /// body from [with_body](RateLimit::with_body), and only
+    /// `Retry-After` is added to the catcher's response. Without
+    /// redirects, the [NotRateLimited] guard fails with the same
+    /// status, and routes without the guard still get their response
+    /// replaced with the rejection.
+    /// Disabled by default.
+    ///
+    pub fn catchers(mut self, enabled: bool) -> Self {
+        self.catchers = enabled;
+        self
+    }
 
-        if let Some(rate_limit) = result {
-            self.apply_rate_limit(res, rate_limit);
+    /// Sets the status of rejected responses.
+    ///
+    /// Defaults to `429 Too Many Requests`. `Retry-After` is sent
+    /// regardless of the status.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `status` isn't a 4xx or 5xx status.
+    ///
+    pub fn reject_status(mut self, status: Status) -> Self {
+        assert!(
+            (400..600).contains(&status.code),
+            "reject status must be a 4xx or 5xx status, got {}",
+            status
+        );
+
+        self.reject_status = status;
+        self
+    }
+
+    /// Sets the body of rejected responses.
+    ///
+    /// The closure receives the rejection, so the body can include
+    /// dynamic values such as the retry delay:
+    ///
+    /// ```no_run
+    ///# use rocket::http::ContentType;
+    ///# use rocket_rate_limit::RateLimit;
+    ///# use rocket_rate_limit::governor::state::keyed::DefaultKeyedStateStore;
+    ///# type Store = DefaultKeyedStateStore<String>;
+    /// let rate_limit = RateLimit::<String, Store>::default()
+    ///     .with_body(|rate_limit| {
+    ///         (
+    ///             ContentType::JSON,
+    ///             format!(
+    ///                 r#"{{"error":"rate_limited","retry_after":{}}}"#,
+    ///                 rate_limit.retry_after_secs()
+    ///             ),
+    ///         )
+    ///     });
+    /// ```
+    ///
+    /// By default, the body is empty.
+    ///
+    pub fn with_body<F>(mut self, body: F) -> Self
+    where
+        F: Fn(&RateLimitResponse) -> (ContentType, String)
+            + Send
+            + Sync
+            + 'static,
+    {
+        self.body = RejectBody::Custom(Box::new(body));
+        self
+    }
+
+    /// Renders rejected responses as RFC 7807 problem documents:
+    ///
+    /// ```json
+    /// {"type":"about:blank","title":"Too Many Requests","status":429,"retryAfter":5}
+    /// ```
+    ///
+    /// The status is the [reject_status](RateLimit::reject_status),
+    /// and `retryAfter` the same as the `Retry-After` header. Requests
+    /// that exceed the burst size get a `detail` instead. Requires the
+    /// `json` feature.
+    ///
+    #[cfg(feature = "json")]
+    pub fn problem_json(mut self) -> Self {
+        self.body = RejectBody::ProblemJson;
+        self
+    }
+
+    /// Sets the `Content-Type` of rejected responses, regardless of
+    /// the body.
+    ///
+    /// Defaults to the content type of the body, and `text/plain`
+    /// for the empty default body.
+    ///
+    pub fn reject_content_type(
+        mut self,
+        content_type: ContentType,
+    ) -> Self {
+        self.reject_content_type = Some(content_type);
+        self
+    }
+
+    /// Sets a fixed body for rejected responses.
+    ///
+    /// See [RateLimit::with_body] for bodies that depend on the
+    /// rejection.
+    ///
+    pub fn with_static_body(
+        self,
+        content_type: ContentType,
+        body: &'static str,
+    ) -> Self {
+        self.with_body(move |_| {
+            (content_type.clone(), body.to_string())
+        })
+    }
+
+    /// Adds configs to a route.
+    ///
+    /// A name ending with `*` is a prefix pattern, e.g. `api_*`
+    /// applies to `api_users_list` and `api_users_get`. Configs added
+    /// for the exact name of a route take precedence over patterns,
+    /// and longer patterns over shorter ones.
+    ///
+    pub fn add<R, I>(&mut self, route_name: R, items_iter: I)
+    where
+        R: AsRef<str>,
+        I: IntoIterator<Item = RateLimitConfig<K, S, C>>,
+    {
+        let route_name = route_name.as_ref();
+
+        let items =
+            self.configs.entry(route_name.to_string()).or_default();
+
+        items.extend(items_iter.into_iter().map(Arc::new));
+
+        sort_by_priority(items);
+    }
+
+    /// Adds a config to several routes, which then share its
+    /// limiter.
+    ///
+    /// Unlike adding a config to each route, requests to any of the
+    /// routes draw from the same buckets, e.g. to allow 20 requests
+    /// per minute across all report endpoints together. The key
+    /// space is shared too, so a key has one bucket for all routes.
+    ///
+    pub fn add_shared<I, R>(
+        &mut self,
+        route_names: I,
+        config: RateLimitConfig<K, S, C>,
+    ) where
+        I: IntoIterator<Item = R>,
+        R: AsRef<str>,
+    {
+        let config = Arc::new(config);
+
+        for route_name in route_names {
+            let items = self
+                .configs
+                .entry(route_name.as_ref().to_string())
+                .or_default();
+
+            items.push(config.clone());
+
+            sort_by_priority(items);
+        }
+    }
+
+    /// Calls `callback` whenever a request is rate limited, e.g. to
+    /// update metrics or for alerting.
+    ///
+    /// This includes rejections from [RateLimit::check].
+    ///
+    pub fn on_limited<F>(mut self, callback: F) -> Self
+    where
+        F: Fn(&Request<'_>, &RateLimitResponse)
+            + Send
+            + Sync
+            + 'static,
+    {
+        self.on_limited = Some(Box::new(callback));
+        self
+    }
+
+    /// Adds concurrency limits to a route.
+    ///
+    /// They are checked after the rate limits, so requests rejected
+    /// by a rate limit never take a permit. Like with
+    /// [add](RateLimit::add), the name can be a prefix pattern.
+    ///
+    pub fn add_concurrency<R, I>(
+        &mut self,
+        route_name: R,
+        items_iter: I,
+    ) where
+        R: AsRef<str>,
+        I: IntoIterator<Item = ConcurrencyLimit<K>>,
+    {
+        self.concurrency
+            .entry(route_name.as_ref().to_string())
+            .or_default()
+            .extend(items_iter);
+    }
+
+    /// Adds configs that apply to every request, regardless of the
+    /// route.
+    ///
+    /// They are checked in addition to the route configs, e.g. to
+    /// cap the overall request rate of the application.
+    ///
+    pub fn global<I>(&mut self, items_iter: I)
+    where
+        I: IntoIterator<Item = RateLimitConfig<K, S, C>>,
+    {
+        self.global.extend(items_iter.into_iter().map(Arc::new));
+
+        sort_by_priority(&mut self.global);
+    }
+
+    /// Adds configs for routes that have no configs of their own.
+    ///
+    /// This includes routes without a name. Unlike
+    /// [global](RateLimit::global) configs, they aren't checked for
+    /// routes added with [add](RateLimit::add).
+    ///
+    pub fn default_configs<I>(&mut self, items_iter: I)
+    where
+        I: IntoIterator<Item = RateLimitConfig<K, S, C>>,
+    {
+        self.defaults.extend(items_iter.into_iter().map(Arc::new));
+
+        sort_by_priority(&mut self.defaults);
+    }
+
+    /// Exempts a key from all rate limits.
+    ///
+    /// Requests for which any filter extracts this key are never
+    /// rate limited, regardless of the route.
+    ///
+    pub fn allow(&mut self, key: K) {
+        self.allowlist.insert(key);
+    }
+
+    /// Names of the routes that have configs of their own, with the
+    /// number of configs of each, in no particular order.
+    ///
+    /// Global and default configs aren't included, since they apply
+    /// to every route.
+    ///
+    pub fn configured_routes(
+        &self,
+    ) -> impl Iterator<Item = (&str, usize)> + '_ {
+        self.configs
+            .iter()
+            .map(|(name, configs)| (name.as_str(), configs.len()))
+    }
+
+    /// Whether `key` is exempt from all limits, i.e. it's
+    /// [allowed](RateLimit::allow) or in one of the
+    /// [allowed networks](RateLimit::allow_network).
+    fn is_exempt(&self, key: &K) -> bool {
+        self.allowlist.contains(key) || self.in_allowed_network(key)
+    }
+
+    /// Whether `key` is an IP address in one of the
+    /// [allowed networks](RateLimit::allow_network).
+    fn in_allowed_network(&self, key: &K) -> bool {
+        let Some(key_ip) = self.key_ip else {
+            return false;
+        };
+        let ip = key_ip(key);
+
+        self.allowed_networks
+            .iter()
+            .any(|network| network.contains(&ip))
+    }
+
+    /// Applies the [namespace](RateLimit::namespace) to a key.
+    fn namespaced(&self, key: K) -> K {
+        match &self.namespace {
+            Some(namespace) => namespace(&key),
+            None => key,
+        }
+    }
+
+    /// Number of requests the fairing let through so far.
+    ///
+    /// Requests of routes without limits are counted as well.
+    ///
+    pub fn allowed_count(&self) -> u64 {
+        self.allowed.load(Ordering::Relaxed)
+    }
+
+    /// Number of requests the fairing rejected so far.
+    ///
+    /// Requests that were delayed and then let through count as
+    /// allowed.
+    ///
+    pub fn denied_count(&self) -> u64 {
+        self.denied.load(Ordering::Relaxed)
+    }
+
+    /// Turns rate limiting back on after [RateLimit::disable].
+    pub fn enable(&self) {
+        self.enabled.store(true, Ordering::Relaxed);
+    }
+
+    /// Lets all requests through, e.g. during an incident, until
+    /// [RateLimit::enable] is called.
+    ///
+    /// Requests aren't counted against any limit in the meantime,
+    /// and manual [checks](RateLimit::check) never reject them.
+    ///
+    pub fn disable(&self) {
+        self.enabled.store(false, Ordering::Relaxed);
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled.load(Ordering::Relaxed)
+    }
+
+    /// Status of a rejected response.
+    fn status_of(&self, rate_limit: &RateLimitResponse) -> Status {
+        if rate_limit.store_unavailable {
+            Status::ServiceUnavailable
+        } else {
+            rate_limit.status.unwrap_or(self.reject_status)
+        }
+    }
+
+    /// Renders a rejection, whose wait has to be
+    /// [advertised](RateLimit::advertised) already.
+    fn apply_rate_limit(
+        &self,
+        res: &mut Response<'_>,
+        rate_limit: &RateLimitResponse,
+    ) {
+        use std::io::Cursor;
+
+        res.set_status(self.status_of(rate_limit));
+        self.apply_retry_after(res, rate_limit);
+        apply_vary(res, rate_limit);
+
+        let (content_type, body) = match &self.body {
+            RejectBody::Custom(body_fn) => body_fn(rate_limit),
+            #[cfg(feature = "json")]
+            RejectBody::ProblemJson => {
+                self.problem_document(rate_limit)
+            }
+            // Replace the content type of e.g. a handler's JSON
+            // response too.
+            RejectBody::Empty => (ContentType::Plain, String::new()),
+        };
+
+        res.set_header(
+            self.reject_content_type.clone().unwrap_or(content_type),
+        );
+        res.set_sized_body(body.len(), Cursor::new(body));
+    }
+
+    /// Sets `Retry-After` to the advertised wait of a rejection.
+    fn apply_retry_after(
+        &self,
+        res: &mut Response<'_>,
+        rate_limit: &RateLimitResponse,
+    ) {
+        if rate_limit.exceeds_burst {
+            return;
+        }
+
+        let value = match self.retry_after_format {
+            RetryAfterFormat::Seconds => {
+                rate_limit.retry_after_secs().to_string()
+            }
+            RetryAfterFormat::HttpDate => {
+                // Rounded up like the seconds, since HTTP dates have
+                // no fractions either.
+                let wait = Duration::from_secs(
+                    rate_limit.retry_after_secs(),
+                );
+
+                httpdate::fmt_http_date(rate_limit.decided_at + wait)
+            }
+        };
+
+        self.set_header(res, Header::new("Retry-After", value));
+    }
+
+    /// Renders an RFC 7807 problem document for a rejection.
+    #[cfg(feature = "json")]
+    fn problem_document(
+        &self,
+        rate_limit: &RateLimitResponse,
+    ) -> (ContentType, String) {
+        use rocket::serde::json::serde_json::{json, Value};
+
+        let status = self.status_of(rate_limit);
+        let mut document = json!({
+            "type": "about:blank",
+            "title": status.reason_lossy(),
+            "status": status.code,
+        });
+
+        if rate_limit.exceeds_burst {
+            document["detail"] = Value::from(
+                "The request exceeds the burst size of the quota.",
+            );
+        } else {
+            document["retryAfter"] =
+                Value::from(rate_limit.retry_after_secs());
+        }
+
+        (
+            ContentType::new("application", "problem+json"),
+            document.to_string(),
+        )
+    }
+
+    /// Adjusts the wait that is sent to the client.
+    ///
+    /// The jitter only ever adds to the wait, so clients are never
+    /// told to retry before the quota allows it. The bounds are
+    /// applied last, though.
+    ///
+    fn advertised(
+        &self,
+        rate_limit: &RateLimitResponse,
+    ) -> RateLimitResponse {
+        let mut rate_limit = *rate_limit;
+
+        // There's no wait to adjust.
+        if rate_limit.exceeds_burst {
+            return rate_limit;
+        }
+
+        if let Some(jitter) = self.retry_jitter {
+            rate_limit.retry_after = rate_limit.retry_after + jitter;
+        }
+
+        if let Some((min, max)) = self.retry_after_bounds {
+            rate_limit.retry_after =
+                rate_limit.retry_after.clamp(min, max);
+        }
+
+        rate_limit
+    }
+
+    fn apply_headers(
+        &self,
+        res: &mut Response<'_>,
+        state: &RateLimitStatus,
+    ) {
+        let reset = as_secs_ceil(state.reset);
+
+        match self.header_style {
+            HeaderStyle::Legacy => {
+                self.set_header(
+                    res,
+                    Header::new(
+                        "X-RateLimit-Limit",
+                        state.limit.to_string(),
+                    ),
+                );
+                self.set_header(
+                    res,
+                    Header::new(
+                        "X-RateLimit-Remaining",
+                        state.remaining.to_string(),
+                    ),
+                );
+                self.set_header(
+                    res,
+                    Header::new(
+                        "X-RateLimit-Reset",
+                        reset.to_string(),
+                    ),
+                );
+            }
+            HeaderStyle::IetfDraft => {
+                self.set_header(
+                    res,
+                    Header::new(
+                        "RateLimit",
+                        format!(
+                            "limit={}, remaining={}, reset={}",
+                            state.limit, state.remaining, reset
+                        ),
+                    ),
+                );
+                self.set_header(
+                    res,
+                    Header::new(
+                        "RateLimit-Policy",
+                        format!(
+                            "{};w={}",
+                            state.limit,
+                            as_secs_ceil(state.window)
+                        ),
+                    ),
+                );
+            }
+        }
+    }
+
+    /// Sets a header, unless it's already set and
+    /// [overwrite_headers](RateLimit::overwrite_headers) is disabled.
+    fn set_header(
+        &self,
+        res: &mut Response<'_>,
+        header: Header<'static>,
+    ) {
+        if self.overwrite_headers
+            || !res.headers().contains(header.name())
+        {
+            res.set_header(header);
+        }
+    }
+}
+
+/// A rejected request.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RateLimitResponse {
+    retry_after: Duration,
+    exceeds_burst: bool,
+    /// Request header the rejecting config is keyed on, see
+    /// [RateLimitConfig::vary].
+    vary: Option<&'static str>,
+    store_unavailable: bool,
+    /// Status of the rejecting config, see
+    /// [RateLimitConfig::reject_status].
+    status: Option<Status>,
+    decided_at: SystemTime,
+}
+
+impl RateLimitResponse {
+    /// Time until the client may retry.
+    ///
+    /// Zero if the request [exceeds the burst
+    /// size](RateLimitResponse::exceeds_burst).
+    ///
+    pub fn retry_after(&self) -> Duration {
+        self.retry_after
+    }
+
+    /// Whether the cost of the request is greater than the burst
+    /// size of the quota.
+    ///
+    /// Such requests can never succeed, so no `Retry-After` header is
+    /// sent for them.
+    ///
+    pub fn exceeds_burst(&self) -> bool {
+        self.exceeds_burst
+    }
+
+    /// Whether the store of the quota couldn't be reached, see
+    /// [RateLimit::on_store_error].
+    ///
+    /// Such requests are rejected with `503 Service Unavailable`.
+    ///
+    pub fn store_unavailable(&self) -> bool {
+        self.store_unavailable
+    }
+
+    /// Wall-clock time at which the client may retry, e.g. for an
+    /// absolute `X-RateLimit-Reset`.
+    ///
+    /// The wait of the limiter is added to the system time when the
+    /// request was checked. `None` if the request [exceeds the burst
+    /// size](RateLimitResponse::exceeds_burst).
+    ///
+    pub fn reset_at(&self) -> Option<SystemTime> {
+        (!self.exceeds_burst)
+            .then(|| self.decided_at + self.retry_after)
+    }
+
+    /// Seconds until the client may retry, as sent in `Retry-After`.
+    ///
+    /// The header only accepts whole seconds, so any sub-second
+    /// remainder is rounded up (a 500ms wait becomes `1`).
+    ///
+    pub fn retry_after_secs(&self) -> u64 {
+        as_secs_ceil(self.retry_after)
+    }
+}
+
+/// Quota state of the config that matched a request.
+///
+/// Handlers can read it with a request guard, e.g. to include the
+/// remaining quota in the response:
+///
+/// ```no_run
+///# use rocket::get;
+///# use rocket_rate_limit::RateLimitStatus;
+/// #[get("/search")]
+/// fn search(status: RateLimitStatus) -> String {
+///     format!("{} requests left", status.remaining())
+/// }
+/// ```
+///
+/// The guard forwards if the route isn't rate limited.
+///
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RateLimitStatus {
+    /// Burst size of the quota.
+    limit: u32,
+    /// Cells left in the bucket after this request.
+    remaining: u32,
+    /// Time until the bucket is fully replenished. For rejected
+    /// requests, this is the same as `retry_after`.
+    reset: Duration,
+    /// Time it takes to replenish the whole burst.
+    window: Duration,
+}
+
+impl RateLimitStatus {
+    /// Creates a status, e.g. for the outcome of an
+    /// [AsyncKeyedStore].
+    ///
+    /// `reset` is the time until the quota is fully replenished, and
+    /// `window` the time it takes to replenish the whole burst.
+    ///
+    pub fn new(
+        limit: u32,
+        remaining: u32,
+        reset: Duration,
+        window: Duration,
+    ) -> Self {
+        RateLimitStatus {
+            limit,
+            remaining,
+            reset,
+            window,
+        }
+    }
+
+    fn from_snapshot(snapshot: &StateSnapshot) -> Self {
+        let quota = snapshot.quota();
+        let limit = quota.burst_size().get();
+        let remaining = snapshot.remaining_burst_capacity();
+
+        RateLimitStatus {
+            limit,
+            remaining,
+            reset: quota.replenish_interval() * (limit - remaining),
+            window: quota_window(&quota),
+        }
+    }
+
+    /// Burst size of the quota.
+    pub fn limit(&self) -> u32 {
+        self.limit
+    }
+
+    /// Requests left before the client is rate limited.
+    pub fn remaining(&self) -> u32 {
+        self.remaining
+    }
+
+    /// Time until the quota is fully replenished.
+    pub fn reset(&self) -> Duration {
+        self.reset
+    }
+
+    /// Time it takes to replenish the whole burst.
+    pub fn window(&self) -> Duration {
+        self.window
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for RateLimitStatus {
+    type Error = Infallible;
+
+    async fn from_request(
+        req: &'r Request<'_>,
+    ) -> request::Outcome<Self, Self::Error> {
+        // The state of the rate limiter with the least remaining
+        // quota, if several are attached.
+        let state = decisions(req)
+            .iter()
+            .filter_map(|(_, result)| result.state)
+            .min_by_key(|state| state.remaining);
+
+        match state {
+            Some(status) => Outcome::Success(status),
+            None => Outcome::Forward(()),
+        }
+    }
+}
+
+/// A request guard that fails if the request is rate limited.
+///
+/// Only needed if redirects are disabled with [RateLimit::redirect].
+/// The guard fails with the [RateLimitResponse] and the
+/// [reject_status](RateLimit::reject_status) of the rejection, and
+/// the fairing replaces the response with the rejection.
+///
+pub struct NotRateLimited;
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for NotRateLimited {
+    type Error = RateLimitResponse;
+
+    async fn from_request(
+        req: &'r Request<'_>,
+    ) -> request::Outcome<Self, Self::Error> {
+        let rejection = decisions(req)
+            .iter()
+            .find_map(|(_, result)| result.rejection);
+
+        match rejection {
+            Some(rejection) => Outcome::Failure((
+                // The fairing resolves the status of its rejections.
+                rejection.status.unwrap_or(Status::TooManyRequests),
+                rejection,
+            )),
+            None => Outcome::Success(NotRateLimited),
+        }
+    }
+}
+
+/// Outcome of checking a request against its configs.
+///
+/// This is also what the fairing stores in the request-local cache.
+/// Since the cache is keyed by type, this type must stay private to
+/// the crate, so no other fairing or guard can read or clobber it.
+///
+#[derive(Clone, Copy, Default)]
+struct RateLimitResult {
+    state: Option<RateLimitStatus>,
+    rejection: Option<RateLimitResponse>,
+}
+
+/// Results of every attached rate limiter for the current request,
+/// by the id of the rate limiter.
+#[derive(Default)]
+struct Decisions(Mutex<Vec<(usize, RateLimitResult)>>);
+
+/// Returns the results of the attached rate limiters.
+fn decisions(req: &Request<'_>) -> Vec<(usize, RateLimitResult)> {
+    req.local_cache(Decisions::default)
+        .0
+        .lock()
+        .unwrap()
+        .clone()
+}
+
+/// Source of the ids that tell attached rate limiters apart.
+static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+
+/// Cancels the connection upgrade, e.g. the WebSocket handshake, of
+/// a rejected request whose handler ran.
+///
+/// Rocket switches protocols whenever the response accepts one the
+/// request asked for, whatever its status. Upgrades can't be removed
+/// from a response, so it's rebuilt with just its headers.
+///
+fn cancel_upgrade(req: &Request<'_>, res: &mut Response<'_>) {
+    let mut protocols = req
+        .headers()
+        .get("Upgrade")
+        .flat_map(|value| value.split(','))
+        .map(str::trim);
+
+    if !protocols.any(|protocol| res.upgrade(protocol).is_some()) {
+        return;
+    }
+
+    let mut rejected = Response::new();
+    for header in res.headers().iter() {
+        rejected.adjoin_header(header.into_owned());
+    }
+
+    *res = rejected;
+}
+
+/// Adds the header the rejecting config is keyed on to `Vary`,
+/// keeping the values already there.
+fn apply_vary(
+    res: &mut Response<'_>,
+    rate_limit: &RateLimitResponse,
+) {
+    if let Some(header_name) = rate_limit.vary {
+        res.adjoin_header(Header::new("Vary", header_name));
+    }
+}
+
+/// Sorts configs in reverse order by priority.
+///
+/// The sort is stable, so configs with the same priority keep the
+/// order they were added in.
+///
+fn sort_by_priority<K, S, C>(
+    configs: &mut [Arc<RateLimitConfig<K, S, C>>],
+) where
+    K: Eq + Clone + Hash,
+    S: KeyedStateStore<K>,
+    C: Clock,
+{
+    configs.sort_by(|a, b| b.priority.cmp(&a.priority));
+}
+
+/// Finds the entry of a route, either by its exact name, or by the
+/// longest matching prefix pattern.
+fn lookup_route<'a, T>(
+    map: &'a HashMap<String, T>,
+    route_name: &str,
+) -> Option<&'a T> {
+    if let Some(entry) = map.get(route_name) {
+        return Some(entry);
+    }
+
+    map.iter()
+        .filter_map(|(pattern, entry)| {
+            let prefix = pattern.strip_suffix('*')?;
+            route_name
+                .starts_with(prefix)
+                .then_some((prefix.len(), entry))
+        })
+        .max_by_key(|(len, _)| *len)
+        .map(|(_, entry)| entry)
+}
+
+/// Hashes a key, so it can be logged without revealing it.
+#[cfg(feature = "tracing")]
+fn key_hash<K: Hash>(key: &K) -> u64 {
+    use std::collections::hash_map::DefaultHasher;
+    use std::hash::Hasher;
+
+    let mut hasher = DefaultHasher::new();
+    key.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Time it takes to replenish the whole burst of a quota.
+fn quota_window(quota: &Quota) -> Duration {
+    quota.replenish_interval() * quota.burst_size().get()
+}
+
+/// Converts a duration to whole seconds, rounding up.
+fn as_secs_ceil(duration: Duration) -> u64 {
+    let secs = duration.as_secs();
+
+    if duration.subsec_nanos() > 0 {
+        secs + 1
+    } else {
+        secs
+    }
+}
+
+/// The limiter of a [RateLimitConfig].
+type Limiter<K, S, C> =
+    RateLimiter<K, S, C, StateInformationMiddleware>;
+
+pub struct RateLimitConfig<K, S, C = DefaultClock>
+where
+    K: Eq + Clone + Hash,
+    S: KeyedStateStore<K>,
+    C: Clock,
+{
+    limiter: ConfigLimiter<K, S, C>,
+    /// Extracts the key of keyed limiters, `None` for direct ones.
+    filter: Option<Box<dyn TryKeyFilter<K> + Send + Sync>>,
+    fail_closed: bool,
+    priority: u32,
+    /// Burst size of the limiter, if it's known, to validate costs.
+    burst: Option<NonZeroU32>,
+    cost: Option<CostFn>,
+    count_when: Option<StatusFn>,
+    blocked: BlockedKeys<K, C>,
+    reset: Option<ResetFn<K>>,
+    vary: Option<&'static str>,
+    reject_status: Option<Status>,
+}
+
+/// The limiter of a [RateLimitConfig::direct] config.
+type DirectLimiter<C> = RateLimiter<
+    NotKeyed,
+    InMemoryState,
+    C,
+    StateInformationMiddleware,
+>;
+
+enum ConfigLimiter<K, S, C>
+where
+    K: Eq + Clone + Hash,
+    S: KeyedStateStore<K>,
+    C: Clock,
+{
+    /// A limiter keyed by the key the filter extracts.
+    Keyed(Arc<Limiter<K, S, C>>),
+    /// A single bucket shared by all requests.
+    Direct(Arc<DirectLimiter<C>>),
+    /// A store that keeps track of the quota itself.
+    Async(Arc<dyn AsyncKeyedStore<K> + Send + Sync>),
+    /// Keyed limiters for the quotas a [QuotaProvider] chooses.
+    Tiered(Arc<TieredLimiter<K, S, C>>),
+    /// Keyed limiters that all check the same key.
+    Layered(Arc<[Arc<Limiter<K, S, C>>]>),
+}
+
+impl<K, S, C> Clone for ConfigLimiter<K, S, C>
+where
+    K: Eq + Clone + Hash,
+    S: KeyedStateStore<K>,
+    C: Clock,
+{
+    fn clone(&self) -> Self {
+        match self {
+            ConfigLimiter::Keyed(limiter) => {
+                ConfigLimiter::Keyed(limiter.clone())
+            }
+            ConfigLimiter::Direct(limiter) => {
+                ConfigLimiter::Direct(limiter.clone())
+            }
+            ConfigLimiter::Async(store) => {
+                ConfigLimiter::Async(store.clone())
+            }
+            ConfigLimiter::Tiered(tiers) => {
+                ConfigLimiter::Tiered(tiers.clone())
+            }
+            ConfigLimiter::Layered(limiters) => {
+                ConfigLimiter::Layered(limiters.clone())
+            }
+        }
+    }
+}
+
+impl<K, S, C> ConfigLimiter<K, S, C>
+where
+    K: Eq + Clone + Hash,
+    S: KeyedStateStore<K>,
+    C: Clock,
+{
+    /// Consumes `cost` cells (one by default) of the bucket of `key`.
+    ///
+    /// Direct limiters ignore the key.
+    ///
+    async fn check(
+        &self,
+        key: Option<&K>,
+        cost: Option<NonZeroU32>,
+        clock: &C,
+    ) -> StoreOutcome {
+        if let (ConfigLimiter::Async(store), Some(key)) = (self, key)
+        {
+            return store
+                .check(key, cost.unwrap_or(NonZeroU32::MIN))
+                .await;
+        }
+
+        match catch_store_errors(|| self.check_sync(key, cost, clock))
+        {
+            (_, true) => StoreOutcome::Unavailable,
+            (outcome, false) => outcome,
+        }
+    }
+
+    /// Checks the governor limiters, which never await.
+    fn check_sync(
+        &self,
+        key: Option<&K>,
+        cost: Option<NonZeroU32>,
+        clock: &C,
+    ) -> StoreOutcome {
+        match (self, key) {
+            (ConfigLimiter::Keyed(limiter), Some(key)) => {
+                governor_outcome(check_key(limiter, key, cost), clock)
+            }
+            (ConfigLimiter::Direct(limiter), _) => governor_outcome(
+                match cost {
+                    Some(cost) => limiter.check_n(cost),
+                    None => Ok(limiter.check()),
+                },
+                clock,
+            ),
+            (ConfigLimiter::Tiered(tiers), Some(key)) => {
+                let limiter = tiers.limiter(key, clock);
+
+                governor_outcome(
+                    check_key(&limiter, key, cost),
+                    clock,
+                )
+            }
+            (ConfigLimiter::Layered(limiters), Some(key)) => {
+                // Every limiter is checked, so the longest wait is
+                // known.
+                let outcomes: Vec<_> = limiters
+                    .iter()
+                    .map(|limiter| {
+                        governor_outcome(
+                            check_key(limiter, key, cost),
+                            clock,
+                        )
+                    })
+                    .collect();
+
+                most_restrictive(outcomes)
+            }
+            (ConfigLimiter::Async(_), Some(_)) => {
+                unreachable!(
+                    "asynchronous stores are checked by `check`"
+                )
+            }
+            (_, None) => {
+                unreachable!(
+                    "keyed limiters are always checked with a key"
+                )
+            }
+        }
+    }
+}
+
+/// Consumes `cost` cells (one by default) of the bucket of `key`.
+fn check_key<K, S, C>(
+    limiter: &Limiter<K, S, C>,
+    key: &K,
+    cost: Option<NonZeroU32>,
+) -> Result<
+    Result<StateSnapshot, NotUntil<C::Instant>>,
+    InsufficientCapacity,
+>
+where
+    K: Eq + Clone + Hash,
+    S: KeyedStateStore<K>,
+    C: Clock,
+{
+    match cost {
+        Some(cost) => limiter.check_key_n(key, cost),
+        None => Ok(limiter.check_key(key)),
+    }
+}
+
+/// Converts the result of a governor check.
+fn governor_outcome<C: Clock>(
+    result: Result<
+        Result<StateSnapshot, NotUntil<C::Instant>>,
+        InsufficientCapacity,
+    >,
+    clock: &C,
+) -> StoreOutcome {
+    match result {
+        Ok(Ok(snapshot)) => StoreOutcome::Allowed(
+            RateLimitStatus::from_snapshot(&snapshot),
+        ),
+        Ok(Err(not_until)) => {
+            let quota = not_until.quota();
+
+            StoreOutcome::Limited(RateLimitStatus {
+                limit: quota.burst_size().get(),
+                remaining: 0,
+                reset: not_until.wait_time_from(clock.now()),
+                window: quota_window(&quota),
+            })
+        }
+        Err(InsufficientCapacity(burst_size)) => {
+            StoreOutcome::ExceedsBurst { burst_size }
+        }
+    }
+}
+
+/// Combines the outcomes of the limiters of a layered config.
+///
+/// A request that exceeds any burst size can never succeed. Otherwise
+/// the longest wait wins, and allowed requests report the limiter
+/// with the fewest requests left.
+///
+fn most_restrictive(outcomes: Vec<StoreOutcome>) -> StoreOutcome {
+    use StoreOutcome::*;
+
+    outcomes
+        .into_iter()
+        .reduce(|a, b| match (a, b) {
+            (Unavailable, _) | (_, Unavailable) => Unavailable,
+            (
+                ExceedsBurst { burst_size: a },
+                ExceedsBurst { burst_size: b },
+            ) => ExceedsBurst {
+                burst_size: a.min(b),
+            },
+            (exceeds @ ExceedsBurst { .. }, _)
+            | (_, exceeds @ ExceedsBurst { .. }) => exceeds,
+            (Limited(a), Limited(b)) => {
+                Limited(if b.reset > a.reset { b } else { a })
+            }
+            (limited @ Limited(_), _) | (_, limited @ Limited(_)) => {
+                limited
+            }
+            (Allowed(a), Allowed(b)) => {
+                Allowed(if b.remaining < a.remaining { b } else { a })
+            }
+        })
+        .expect("layered configs have at least one limiter")
+}
+
+thread_local! {
+    /// Whether a synchronous store failed during the check running on
+    /// this thread, `None` outside of checks. See
+    /// [report_store_error].
+    static STORE_ERROR: Cell<Option<bool>> = Cell::new(None);
+}
+
+/// Runs a check of governor limiters, and tells whether a store
+/// reported an error during it.
+///
+/// The check can't await, so it stays on this thread and no other
+/// check runs on it in the meantime.
+///
+fn catch_store_errors<T>(check: impl FnOnce() -> T) -> (T, bool) {
+    let outer = STORE_ERROR.with(|error| error.replace(Some(false)));
+    let result = check();
+    let failed = STORE_ERROR.with(|error| error.replace(outer));
+
+    (result, failed == Some(true))
+}
+
+/// Tells the rate limiter that the backend of a governor state store
+/// can't be reached.
+///
+/// Governor gives state stores no way to return errors, so stores
+/// call this from [StateStore::measure_and_replace] and answer with
+/// an empty state. The result of the check is then discarded, and
+/// the request is handled as [RateLimit::on_store_error] says.
+/// Calls outside of a check are ignored.
+///
+/// [StateStore::measure_and_replace]: governor::state::StateStore::measure_and_replace
+///
+pub fn report_store_error() {
+    STORE_ERROR.with(|error| {
+        if error.get().is_some() {
+            error.set(Some(true));
+        }
+    });
+}
+
+/// Computes the cost of a request.
+type CostFn = Box<dyn Fn(&Request<'_>) -> NonZeroU32 + Send + Sync>;
+
+/// Decides whether a response counts against the quota.
+type StatusFn = Arc<dyn Fn(Status) -> bool + Send + Sync>;
+
+/// Keys of a [RateLimitConfig::count_when] config whose quota ran
+/// out, with the time the next request can be counted again.
+///
+/// Direct limiters use the `None` key.
+///
+type BlockedKeys<K, C> = Arc<
+    Mutex<
+        HashMap<Option<K>, (<C as Clock>::Instant, RateLimitStatus)>,
+    >,
+>;
+
+/// Gives a key of a [RateLimitConfig::resettable] config its full
+/// burst back.
+type ResetFn<K> = Box<dyn Fn(&K) + Send + Sync>;
+
+/// Prefixes a key with the namespace of the rate limiter.
+type NamespaceFn<K> = Box<dyn Fn(&K) -> K + Send + Sync>;
+
+/// Consumes the quota of a deferred config once the response status
+/// is known.
+type CommitFn =
+    Box<dyn FnOnce(Status) -> BoxFuture<'static, ()> + Send>;
+
+/// Consumptions of deferred configs that wait for the response.
+#[derive(Default)]
+struct PendingCommits(Mutex<Vec<CommitFn>>);
+
+impl<K, S> RateLimitConfig<K, S, DefaultClock>
+where
+    K: Eq + Clone + Hash,
+    S: KeyedStateStore<K>,
+{
+    /// Creates a config with a keyed limiter for `quota`, using a new
+    /// default store.
+    ///
+    /// Unlike [RateLimitConfig::new], this knows the quota, so
+    /// [RateLimitConfig::cost] can check costs against it.
+    ///
+    pub fn keyed(
+        quota: Quota,
+        filter: Box<dyn KeyFilter<K> + Send + Sync>,
+    ) -> Self
+    where
+        S: Default,
+    {
+        let limiter = RateLimiter::new(
+            quota,
+            S::default(),
+            &DefaultClock::default(),
+        );
+
+        RateLimitConfig::new(limiter, filter)
+            .known_burst(quota.burst_size())
+    }
+
+    /// Creates a config that allows at most `limit` requests in any
+    /// `window`, see [SlidingWindowLog].
+    ///
+    /// Rate limiters with another clock, e.g. a fake one in tests,
+    /// use [RateLimitConfig::sliding_window_with_clock].
+    ///
+    pub fn sliding_window(
+        limit: NonZeroU32,
+        window: Duration,
+        filter: Box<dyn KeyFilter<K> + Send + Sync>,
+    ) -> Self
+    where
+        K: Send + Sync + 'static,
+    {
+        RateLimitConfig::sliding_window_with_clock(
+            limit,
+            window,
+            filter,
+            &DefaultClock::default(),
+        )
+    }
+}
+
+impl<K, S, C> RateLimitConfig<K, S, C>
+where
+    K: Eq + Clone + Hash,
+    S: KeyedStateStore<K>,
+    C: Clock,
+{
+    pub fn new(
+        limiter: RateLimiter<K, S, C>,
+        filter: Box<dyn KeyFilter<K> + Send + Sync>,
+    ) -> Self {
+        RateLimitConfig::with_limiter(
+            ConfigLimiter::Keyed(Arc::new(
+                limiter
+                    .with_middleware::<StateInformationMiddleware>(),
+            )),
+            Some(Box::new(InfallibleFilter(filter))),
+        )
+    }
+
+    /// Creates a config whose filter can fail.
+    ///
+    /// By default, the config is skipped if the filter fails, see
+    /// [RateLimitConfig::fail_closed].
+    ///
+    pub fn try_new(
+        limiter: RateLimiter<K, S, C>,
+        filter: Box<dyn TryKeyFilter<K> + Send + Sync>,
+    ) -> Self {
+        RateLimitConfig::with_limiter(
+            ConfigLimiter::Keyed(Arc::new(
+                limiter
+                    .with_middleware::<StateInformationMiddleware>(),
+            )),
+            Some(filter),
+        )
+    }
+
+    /// Creates a config that checks requests against an
+    /// [AsyncKeyedStore] instead of a governor limiter.
+    ///
+    pub fn with_store<T>(
+        store: T,
+        filter: Box<dyn KeyFilter<K> + Send + Sync>,
+    ) -> Self
+    where
+        T: AsyncKeyedStore<K> + Send + Sync + 'static,
+    {
+        RateLimitConfig::with_limiter(
+            ConfigLimiter::Async(Arc::new(store)),
+            Some(Box::new(InfallibleFilter(filter))),
+        )
+    }
+
+    /// Like [RateLimitConfig::sliding_window], with the log using
+    /// `clock`, which should be the clock of the rate limiter.
+    ///
+    pub fn sliding_window_with_clock(
+        limit: NonZeroU32,
+        window: Duration,
+        filter: Box<dyn KeyFilter<K> + Send + Sync>,
+        clock: &C,
+    ) -> Self
+    where
+        K: Send + Sync + 'static,
+        C: Send + Sync + 'static,
+    {
+        RateLimitConfig::with_store(
+            SlidingWindowLog::new(limit, window, clock),
+            filter,
+        )
+        .known_burst(limit)
+    }
+
+    /// Creates a config that allows `limit` requests per wall-clock
+    /// `window`, see [FixedWindow].
+    ///
+    /// # Panics
+    ///
+    /// Panics if `window` is zero.
+    ///
+    pub fn fixed_window(
+        limit: NonZeroU32,
+        window: Duration,
+        filter: Box<dyn KeyFilter<K> + Send + Sync>,
+    ) -> Self
+    where
+        K: Send + Sync + 'static,
+    {
+        RateLimitConfig::with_store(
+            FixedWindow::new(limit, window),
+            filter,
+        )
+        .known_burst(limit)
+    }
+
+    /// Creates a config that checks a key against several limiters,
+    /// e.g. a cap per second and a total per day.
+    ///
+    /// Requests are rejected if any of the limiters rejects them,
+    /// with the longest wait of the rejecting limiters. Every limiter
+    /// counts the request, even if another one rejects it.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `limiters` is empty.
+    ///
+    pub fn layered(
+        limiters: Vec<RateLimiter<K, S, C>>,
+        filter: Box<dyn KeyFilter<K> + Send + Sync>,
+    ) -> Self {
+        assert!(
+            !limiters.is_empty(),
+            "layered configs need at least one limiter"
+        );
+
+        RateLimitConfig::with_limiter(
+            ConfigLimiter::Layered(
+                limiters
+                    .into_iter()
+                    .map(|limiter| {
+                        Arc::new(limiter.with_middleware::<
+                            StateInformationMiddleware,
+                        >())
+                    })
+                    .collect(),
+            ),
+            Some(Box::new(InfallibleFilter(filter))),
+        )
+    }
+
+    /// Creates a config whose quota depends on the key.
+    ///
+    /// Keys with the same quota share a keyed limiter, which is
+    /// created with a default store the first time the quota is
+    /// chosen, so no limiter is created per request:
+    ///
+    /// ```no_run
+    ///# use governor::Quota;
+    ///# use governor::state::keyed::DefaultKeyedStateStore;
+    ///# use rocket_rate_limit::{HeaderKeyFilter, QuotaTable, RateLimitConfig};
+    ///# use std::num::NonZeroU32;
+    ///# let (free, gold) = (
+    ///#     Quota::per_minute(NonZeroU32::new(60).unwrap()),
+    ///#     Quota::per_minute(NonZeroU32::new(600).unwrap()),
+    ///# );
+    /// let config: RateLimitConfig<String, DefaultKeyedStateStore<String>> =
+    ///     RateLimitConfig::tiered(
+    ///         QuotaTable::new(free).with("acme-key".to_string(), gold),
+    ///         Box::new(HeaderKeyFilter::new("x-api-key")),
+    ///     );
+    /// ```
+    ///
+    /// If the quota of a key changes, the key starts with a full
+    /// burst in the limiter of its new quota.
+    ///
+    pub fn tiered<P>(
+        provider: P,
+        filter: Box<dyn KeyFilter<K> + Send + Sync>,
+    ) -> Self
+    where
+        S: Default,
+        P: QuotaProvider<K> + Send + Sync + 'static,
+    {
+        RateLimitConfig::with_limiter(
+            ConfigLimiter::Tiered(Arc::new(TieredLimiter::new(
+                Box::new(provider),
+            ))),
+            Some(Box::new(InfallibleFilter(filter))),
+        )
+    }
+
+    /// Creates a config with a single bucket for all requests.
+    ///
+    /// Unlike keyed limiters, this doesn't need a filter:
+    ///
+    /// ```no_run
+    ///# use governor::{Quota, RateLimiter};
+    ///# use governor::state::keyed::DefaultKeyedStateStore;
+    ///# use rocket_rate_limit::RateLimitConfig;
+    ///# use std::num::NonZeroU32;
+    /// // Cap the whole route at 5 requests per second.
+    /// let config: RateLimitConfig<String, DefaultKeyedStateStore<String>> =
+    ///     RateLimitConfig::direct(RateLimiter::direct(
+    ///         Quota::per_second(NonZeroU32::new(5).unwrap()),
+    ///     ));
+    /// ```
+    ///
+    pub fn direct(
+        limiter: RateLimiter<NotKeyed, InMemoryState, C>,
+    ) -> Self {
+        RateLimitConfig::with_limiter(
+            ConfigLimiter::Direct(Arc::new(
+                limiter
+                    .with_middleware::<StateInformationMiddleware>(),
+            )),
+            None,
+        )
+    }
+
+    fn with_limiter(
+        limiter: ConfigLimiter<K, S, C>,
+        filter: Option<Box<dyn TryKeyFilter<K> + Send + Sync>>,
+    ) -> Self {
+        RateLimitConfig {
+            limiter,
+            filter,
+            fail_closed: false,
+            priority: 0,
+            burst: None,
+            cost: None,
+            count_when: None,
+            blocked: Arc::default(),
+            reset: None,
+            vary: None,
+            reject_status: None,
+        }
+    }
+
+    /// Records the burst size of the limiter, for
+    /// [RateLimitConfig::cost] to check costs against.
+    pub(crate) fn known_burst(mut self, burst: NonZeroU32) -> Self {
+        self.burst = Some(burst);
+        self
+    }
+
+    /// Rejects requests for which the filter fails, instead of
+    /// skipping the config.
+    ///
+    /// Useful when the key comes from e.g. a database, and requests
+    /// shouldn't go unlimited while it's down. Disabled by default.
+    ///
+    pub fn fail_closed(mut self, enabled: bool) -> Self {
+        self.fail_closed = enabled;
+        self
+    }
+
+    /// Sets the priority of the config.
+    ///
+    /// Configs with a higher priority are checked first. Configs with
+    /// the same priority are checked in the order they were added,
+    /// also across several calls to [RateLimit::add].
+    ///
+    pub fn priority(mut self, priority: u32) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    /// Sets how many cells of the quota each request consumes.
+    ///
+    /// Defaults to one. Requests costing more than the burst size
+    /// of the quota could never succeed, so such costs are rejected
+    /// right away if the config knows its quota, i.e. it was created
+    /// with [RateLimitConfig::keyed],
+    /// [RateLimitConfig::sliding_window],
+    /// [RateLimitConfig::fixed_window] or [RouteBuilder::limit].
+    ///
+    /// Governor limiters don't expose their quota, so the cost can't
+    /// be checked for configs created from a limiter, nor for costs of
+    /// [RateLimitConfig::cost_fn]. Such requests are rejected at
+    /// runtime without a `Retry-After`, see
+    /// [RateLimitResponse::exceeds_burst].
+    ///
+    /// # Panics
+    ///
+    /// Panics if `cost` is greater than the known burst size of the
+    /// quota.
+    ///
+    pub fn cost(self, cost: NonZeroU32) -> Self {
+        if let Some(burst) = self.burst {
+            assert!(
+                cost <= burst,
+                "cost {} exceeds the burst size {} of the quota, so \
+                 requests could never succeed",
+                cost,
+                burst
+            );
+        }
+
+        self.cost_fn(move |_| cost)
+    }
+
+    /// Computes how many cells of the quota a request consumes.
+    ///
+    /// Useful for e.g. bulk endpoints, where the cost depends on the
+    /// size of the request:
+    ///
+    /// ```no_run
+    ///# use governor::{Quota, RateLimiter};
+    ///# use governor::state::keyed::DefaultKeyedStateStore;
+    ///# use rocket_rate_limit::{IpKeyFilter, RateLimitConfig};
+    ///# use std::num::NonZeroU32;
+    /// let config: RateLimitConfig<String, DefaultKeyedStateStore<String>> =
+    ///     RateLimitConfig::new(
+    ///         RateLimiter::keyed(Quota::per_minute(NonZeroU32::new(100).unwrap())),
+    ///         Box::new(IpKeyFilter),
+    ///     )
+    ///     .cost_fn(|req| {
+    ///         let page_size = req
+    ///             .query_value::<u32>("per_page")
+    ///             .and_then(Result::ok)
+    ///             .unwrap_or(1);
+    ///
+    ///         NonZeroU32::new(page_size.clamp(1, 100)).unwrap()
+    ///     });
+    /// ```
+    ///
+    /// Without a cost function, every request costs one cell.
+    /// Requests costing more than the burst size of the quota are
+    /// always rejected.
+    ///
+    pub fn cost_fn<F>(mut self, cost: F) -> Self
+    where
+        F: Fn(&Request<'_>) -> NonZeroU32 + Send + Sync + 'static,
+    {
+        self.cost = Some(Box::new(cost));
+        self
+    }
+
+    /// Only counts requests whose response status matches
+    /// `predicate`, e.g. failed logins:
+    ///
+    /// ```no_run
+    ///# use governor::{Quota, RateLimiter};
+    ///# use governor::state::keyed::DefaultKeyedStateStore;
+    ///# use rocket_rate_limit::{IpKeyFilter, RateLimitConfig};
+    ///# use std::num::NonZeroU32;
+    /// let config: RateLimitConfig<String, DefaultKeyedStateStore<String>> =
+    ///     RateLimitConfig::new(
+    ///         RateLimiter::keyed(Quota::per_minute(NonZeroU32::new(5).unwrap())),
+    ///         Box::new(IpKeyFilter),
+    ///     )
+    ///     .count_when(|status| status.code == 401 || status.code == 403);
+    /// ```
+    ///
+    /// The quota is consumed when the response is ready, so requests
+    /// are only rejected once the counted responses used it up.
+    /// Requests that are in flight at the same time are all let
+    /// through, and [RateLimit::check] never consumes the quota of
+    /// such configs.
+    ///
+    pub fn count_when<F>(mut self, predicate: F) -> Self
+    where
+        F: Fn(Status) -> bool + Send + Sync + 'static,
+    {
+        self.count_when = Some(Arc::new(predicate));
+        self
+    }
+
+    /// Gives the quota back for requests whose response status
+    /// matches `predicate`, e.g. to only charge for errors:
+    ///
+    /// ```no_run
+    ///# use governor::{Quota, RateLimiter};
+    ///# use governor::state::keyed::DefaultKeyedStateStore;
+    ///# use rocket_rate_limit::{IpKeyFilter, RateLimitConfig};
+    ///# use std::num::NonZeroU32;
+    /// let config: RateLimitConfig<String, DefaultKeyedStateStore<String>> =
+    ///     RateLimitConfig::new(
+    ///         RateLimiter::keyed(Quota::per_minute(NonZeroU32::new(5).unwrap())),
+    ///         Box::new(IpKeyFilter),
+    ///     )
+    ///     .refund_on(|status| status.class().is_success());
+    /// ```
+    ///
+    /// Governor can't give cells back, so this is the inverse of
+    /// [count_when](RateLimitConfig::count_when), and replaces it:
+    /// the quota is only consumed once the response is known not to
+    /// be refunded. Until then, requests are only rejected if the
+    /// counted responses used up the quota.
+    ///
+    /// When several configs match a request, such a config only
+    /// takes part in picking the longest wait while its quota is used
+    /// up. A request rejected by any config isn't counted by it.
+    ///
+    pub fn refund_on<F>(self, predicate: F) -> Self
+    where
+        F: Fn(Status) -> bool + Send + Sync + 'static,
+    {
+        self.count_when(move |status| !predicate(status))
+    }
+
+    /// Sets the status of requests this config rejects, instead of
+    /// the [reject_status](RateLimit::reject_status) of the rate
+    /// limiter.
+    ///
+    /// Useful to tell overload from throttling, e.g. a global config
+    /// that caps the total traffic with `503 Service Unavailable`,
+    /// next to per-client configs with `429 Too Many Requests`. If
+    /// several configs reject a request, the status of the one with
+    /// the longest wait is used.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `status` isn't a 4xx or 5xx status.
+    ///
+    pub fn reject_status(mut self, status: Status) -> Self {
+        assert!(
+            (400..600).contains(&status.code),
+            "reject status must be a 4xx or 5xx status, got {}",
+            status
+        );
+
+        self.reject_status = Some(status);
+        self
+    }
+
+    /// Adds `header_name` to the `Vary` header of responses the
+    /// config rejects.
+    ///
+    /// Use it with filters that key on a request header, e.g.
+    /// `Authorization`, so shared caches don't serve the rejection
+    /// of one client to another.
+    ///
+    pub fn vary(mut self, header_name: &'static str) -> Self {
+        self.vary = Some(header_name);
+        self
+    }
+
+    /// Returns the governor limiter of a keyed config, e.g. to check
+    /// keys manually or to inspect its state.
+    ///
+    /// The limiter reports its state through governor's
+    /// `StateInformationMiddleware`. Returns `None` for single
+    /// bucket, tiered and [AsyncKeyedStore] configs. Limiters can be
+    /// shared between routes and requests, so there is no mutable
+    /// variant, but governor's limiters don't need one.
+    ///
+    pub fn limiter(
+        &self,
+    ) -> Option<&RateLimiter<K, S, C, StateInformationMiddleware>>
+    {
+        match &self.limiter {
+            ConfigLimiter::Keyed(limiter) => Some(limiter),
+            _ => None,
+        }
+    }
+
+    /// Returns the state of `key` if its quota ran out, with the
+    /// time until it can be counted again as the reset.
+    fn blocked_until(
+        &self,
+        key: &Option<K>,
+        now: C::Instant,
+    ) -> Option<RateLimitStatus> {
+        let mut blocked = self.blocked.lock().unwrap();
+        let &(until, state) = blocked.get(key)?;
+
+        if until <= now {
+            blocked.remove(key);
+            return None;
+        }
+
+        Some(RateLimitStatus {
+            reset: until.duration_since(now).into(),
+            ..state
+        })
+    }
+}
+
+impl<K, S, C> RateLimitConfig<K, SharedStore<S>, C>
+where
+    K: Eq + Clone + Hash,
+    S: KeyedStateStore<K> + Send + Sync + 'static,
+    C: Clock,
+{
+    /// Allows [RateLimit::reset] to clear keys of this config.
+    ///
+    /// `store` has to be a clone of the store of the limiter, see
+    /// [SharedStore].
+    ///
+    pub fn resettable(mut self, store: &SharedStore<S>) -> Self {
+        let store = store.clone();
+
+        self.reset = Some(Box::new(move |key| store.reset(key)));
+        self
+    }
+}
+
+impl<K, S, C> RateLimitConfig<K, S, C>
+where
+    K: Eq + Clone + Hash + Send + Sync + 'static,
+    S: KeyedStateStore<K> + Send + Sync + 'static,
+    C: Clock + Send + Sync + 'static,
+{
+    /// Defers consuming the quota of a [RateLimitConfig::count_when]
+    /// config until the response status is known.
+    ///
+    /// Returns `None` for configs that count every request.
+    ///
+    fn deferred(
+        &self,
+        key: Option<K>,
+        cost: Option<NonZeroU32>,
+        clock: &C,
+    ) -> Option<CommitFn> {
+        let count_when = self.count_when.clone()?;
+        let limiter = self.limiter.clone();
+        let blocked = self.blocked.clone();
+        let clock = clock.clone();
+
+        Some(Box::new(move |status| {
+            Box::pin(async move {
+                if !count_when(status) {
+                    return;
+                }
+
+                let needed = cost.map_or(1, NonZeroU32::get);
+                let state = match limiter
+                    .check(key.as_ref(), cost, &clock)
+                    .await
+                {
+                    StoreOutcome::Allowed(state)
+                        if state.remaining >= needed =>
+                    {
+                        return;
+                    }
+                    // The bucket is empty now, so find out when the
+                    // next request fits. A second check can't consume
+                    // anything unless a cell was replenished in
+                    // between.
+                    StoreOutcome::Allowed(_) => {
+                        match limiter
+                            .check(key.as_ref(), cost, &clock)
+                            .await
+                        {
+                            StoreOutcome::Limited(state) => state,
+                            _ => return,
+                        }
+                    }
+                    StoreOutcome::Limited(state) => state,
+                    // The request can never be counted.
+                    StoreOutcome::ExceedsBurst { .. } => return,
+                    // There's nothing to count against.
+                    StoreOutcome::Unavailable => return,
+                };
+
+                let now = clock.now();
+                let mut blocked = blocked.lock().unwrap();
+
+                // Drop keys that can be counted again, so the map
+                // doesn't grow forever.
+                blocked.retain(|_, (until, _)| *until > now);
+                blocked.insert(
+                    key,
+                    (now + Nanos::from(state.reset), state),
+                );
+            })
+        }))
+    }
+}
+
+impl<K, S, C> RateLimit<K, S, C>
+where
+    S: KeyedStateStore<K> + Send + Sync + 'static,
+    K: Eq + Clone + Hash + Send + Sync + 'static,
+    C: Clock + Send + Sync + 'static,
+{
+    /// Clears the state of `key` for a route, e.g. when an admin
+    /// lifts the throttle of a user.
+    ///
+    /// The next request of the key starts with a full burst. Only
+    /// configs created with [RateLimitConfig::resettable] can be
+    /// reset, since governor's stores can't be reached through their
+    /// limiters. [count_when](RateLimitConfig::count_when) configs
+    /// and [bans](RateLimit::ban_policy) of the key are cleared too.
+    ///
+    pub fn reset(&self, route_name: &str, key: &K) {
+        let Some(route_configs) =
+            lookup_route(&self.configs, route_name)
+        else {
+            return;
+        };
+        let key = &self.namespaced(key.clone());
+
+        for cfg in route_configs {
+            if let Some(reset) = &cfg.reset {
+                reset(key);
+            }
+
+            cfg.blocked.lock().unwrap().remove(&Some(key.clone()));
+        }
+
+        if let Some(ban) = &self.ban {
+            ban.clear(key);
+        }
+    }
+
+    /// Checks the rate limits of a route without going through the
+    /// fairing.
+    ///
+    /// This can be used to enforce limits from a handler or a
+    /// custom guard. The check counts against the quota just like a
+    /// request to the route would, except for
+    /// [count_when](RateLimitConfig::count_when) configs, which need
+    /// a response. Returns a [RateLimitResponse] if the request
+    /// should be rejected.
+    ///
+    pub async fn check(
+        &self,
+        req: &Request<'_>,
+        route_name: &str,
+    ) -> Option<RateLimitResponse> {
+        if !self.is_enabled() {
+            return None;
+        }
+
+        let mut commits = Vec::new();
+
+        self.check_route(req, Some(route_name), &mut commits)
+            .await
+            .rejection
+    }
+
+    /// Checks the limits of the route a request is routed to.
+    ///
+    /// Also returns the key the rejection counts against as a
+    /// violation, which is left for the caller to report.
+    ///
+    async fn check_rate_limit(
+        &self,
+        req: &Request<'_>,
+        route: Option<&Route>,
+        commits: &mut Vec<CommitFn>,
+    ) -> (RateLimitResult, Option<K>) {
+        // Unnamed routes are reported once, on ignition.
+        let route_name =
+            route.and_then(|route| route.name.as_deref());
+
+        let mut violator = None;
+        let result = self
+            .check_configs(req, route_name, commits, &mut violator)
+            .await;
+
+        (result, violator)
+    }
+
+    async fn check_route(
+        &self,
+        req: &Request<'_>,
+        route_name: Option<&str>,
+        commits: &mut Vec<CommitFn>,
+    ) -> RateLimitResult {
+        let mut violator = None;
+        let result = self
+            .check_configs(req, route_name, commits, &mut violator)
+            .await;
+
+        self.report(req, &result, violator.as_ref());
+        result
+    }
+
+    /// Records the violation of a rejected request towards a ban
+    /// and calls the [on_limited](RateLimit::on_limited) callback.
+    fn report(
+        &self,
+        req: &Request<'_>,
+        result: &RateLimitResult,
+        violator: Option<&K>,
+    ) {
+        if let (Some(ban), Some(key)) = (&self.ban, violator) {
+            ban.violated(key, self.clock.now());
+        }
+
+        if let (Some(on_limited), Some(rejection)) =
+            (&self.on_limited, &result.rejection)
+        {
+            on_limited(req, rejection);
+        }
+    }
+
+    /// Checks the configs of a route, setting `violated` to the key
+    /// of the config that rejects the request, if it's keyed.
+    async fn check_configs(
+        &self,
+        req: &Request<'_>,
+        route_name: Option<&str>,
+        commits: &mut Vec<CommitFn>,
+        violated: &mut Option<K>,
+    ) -> RateLimitResult {
+        let mut result = RateLimitResult::default();
+        // Key of the config that rejects the request, if it's keyed.
+        let mut violator = None;
+
+        // Routes without configs of their own fall back to the
+        // defaults.
+        let route_configs = route_name
+            .and_then(|name| lookup_route(&self.configs, name))
+            .unwrap_or(&self.defaults);
+
+        // All matching configs are checked, since the rejection with
+        // the longest wait wins.
+        for cfg in route_configs.iter().chain(&self.global) {
+            let key = match &cfg.filter {
+                Some(filter) => {
+                    let key = match filter.try_key(req).await {
+                        Ok(Some(key)) => key,
+                        Ok(None) => continue,
+                        #[cfg_attr(
+                            not(feature = "tracing"),
+                            allow(unused_variables)
+                        )]
+                        Err(error) => {
+                            #[cfg(feature = "tracing")]
+                            tracing::warn!(
+                                route = ?route_name,
+                                %error,
+                                fail_closed = cfg.fail_closed,
+                                "failed to extract the key"
+                            );
+
+                            if !cfg.fail_closed && !error.rejects() {
+                                continue;
+                            }
+
+                            commits.clear();
+                            return RateLimitResult {
+                                state: None,
+                                rejection: Some(RateLimitResponse {
+                                    retry_after: Duration::ZERO,
+                                    exceeds_burst: false,
+                                    vary: cfg.vary,
+                                    store_unavailable: false,
+                                    status: cfg.reject_status,
+                                    decided_at: SystemTime::now(),
+                                }),
+                            };
+                        }
+                    };
+
+                    if self.is_exempt(&key) {
+                        #[cfg(feature = "tracing")]
+                        tracing::debug!(
+                            route = ?route_name,
+                            key = key_hash(&key),
+                            "key is allowlisted"
+                        );
+
+                        commits.clear();
+                        return RateLimitResult::default();
+                    }
+
+                    let key = self.namespaced(key);
+
+                    if let Some(retry_after) =
+                        self.ban.as_ref().and_then(|ban| {
+                            ban.banned(&key, self.clock.now())
+                        })
+                    {
+                        #[cfg(feature = "tracing")]
+                        tracing::debug!(
+                            route = ?route_name,
+                            key = key_hash(&key),
+                            ?retry_after,
+                            "key is banned"
+                        );
+
+                        commits.clear();
+                        return RateLimitResult {
+                            state: None,
+                            rejection: Some(RateLimitResponse {
+                                retry_after,
+                                exceeds_burst: false,
+                                vary: cfg.vary,
+                                store_unavailable: false,
+                                status: cfg.reject_status,
+                                decided_at: SystemTime::now(),
+                            }),
+                        };
+                    }
+
+                    Some(key)
+                }
+                None => None,
+            };
+            let cost = cfg.cost.as_ref().map(|cost| cost(req));
+
+            let outcome = if cfg.count_when.is_some() {
+                // Deferred configs only reject keys whose quota ran out,
+                // the quota is consumed in `on_response`.
+                match cfg.blocked_until(&key, self.clock.now()) {
+                    Some(state) => StoreOutcome::Limited(state),
+                    None => {
+                        commits.extend(cfg.deferred(
+                            key,
+                            cost,
+                            &self.clock,
+                        ));
+                        continue;
+                    }
+                }
+            } else {
+                cfg.limiter
+                    .check(key.as_ref(), cost, &self.clock)
+                    .await
+            };
+
+            match outcome {
+                StoreOutcome::Allowed(state) => {
+                    #[cfg(feature = "tracing")]
+                    tracing::debug!(
+                        route = ?route_name,
+                        key = ?key.as_ref().map(key_hash),
+                        remaining = state.remaining,
+                        "request allowed"
+                    );
+
+                    // Report the state of the highest priority config.
+                    result.state.get_or_insert(state);
+                }
+                StoreOutcome::Limited(state) => {
+                    let retry_after = state.reset;
+
+                    #[cfg(feature = "tracing")]
+                    tracing::debug!(
+                        route = ?route_name,
+                        key = ?key.as_ref().map(key_hash),
+                        ?retry_after,
+                        "request rate limited"
+                    );
+
+                    // Report the longest wait, so clients don't retry
+                    // into another exhausted limit.
+                    if result.rejection.is_some_and(|rejection| {
+                        rejection.retry_after >= retry_after
+                    }) {
+                        continue;
+                    }
+
+                    result.state = Some(state);
+                    result.rejection = Some(RateLimitResponse {
+                        retry_after,
+                        exceeds_burst: false,
+                        vary: cfg.vary,
+                        store_unavailable: false,
+                        status: cfg.reject_status,
+                        decided_at: SystemTime::now(),
+                    });
+                    violator = key;
+                }
+                StoreOutcome::ExceedsBurst { burst_size } => {
+                    #[cfg(feature = "tracing")]
+                    tracing::debug!(
+                        route = ?route_name,
+                        key = ?key.as_ref().map(key_hash),
+                        burst_size,
+                        "request exceeds burst size"
+                    );
+
+                    // The request can never succeed, so there's no
+                    // point in retrying.
+                    result.state = Some(RateLimitStatus {
+                        limit: burst_size,
+                        remaining: 0,
+                        reset: Duration::ZERO,
+                        window: Duration::ZERO,
+                    });
+                    result.rejection = Some(RateLimitResponse {
+                        retry_after: Duration::ZERO,
+                        exceeds_burst: true,
+                        vary: cfg.vary,
+                        store_unavailable: false,
+                        status: cfg.reject_status,
+                        decided_at: SystemTime::now(),
+                    });
+
+                    commits.clear();
+                    return result;
+                }
+                StoreOutcome::Unavailable => {
+                    log::error!(
+                        "the store of route {:?} is unavailable, \
+                         policy {:?}",
+                        route_name,
+                        self.on_store_error
+                    );
+
+                    #[cfg(feature = "tracing")]
+                    tracing::error!(
+                        route = ?route_name,
+                        key = ?key.as_ref().map(key_hash),
+                        policy = ?self.on_store_error,
+                        "the store is unavailable"
+                    );
+
+                    if self.on_store_error
+                        == StoreErrorPolicy::FailOpen
+                    {
+                        continue;
+                    }
+
+                    commits.clear();
+                    return RateLimitResult {
+                        state: None,
+                        rejection: Some(RateLimitResponse {
+                            retry_after: Duration::ZERO,
+                            exceeds_burst: false,
+                            vary: cfg.vary,
+                            store_unavailable: true,
+                            status: None,
+                            decided_at: SystemTime::now(),
+                        }),
+                    };
+                }
+            }
+        }
+
+        *violated = violator;
+        result
+    }
+
+    /// Finds the route Rocket will route a request to.
+    ///
+    /// Like Rocket's router, this picks the matching route with the
+    /// lowest rank. Routes that forward to the next rank can't be
+    /// predicted, so the first candidate is always used.
+    ///
+    fn route_of<'r>(
+        &'r self,
+        req: &'r Request<'_>,
+    ) -> Option<&'r Route> {
+        match self.routes.get() {
+            Some(routes) => routes.route(req),
+            // The routes are only captured on liftoff.
+            None => req
+                .rocket()
+                .routes()
+                .filter(|route| route.matches(req))
+                .min_by_key(|route| route.rank),
+        }
+    }
+
+    /// Like [route_of](RateLimit::route_of), but only finds routes
+    /// that may have limits of their own.
+    ///
+    /// Returns `None` without looking at the routes if no limits are
+    /// tied to a route, or none of the routes the request can match
+    /// has any. Other routes only get the default and global configs
+    /// anyway.
+    ///
+    fn matched_route<'r>(
+        &'r self,
+        req: &'r Request<'_>,
+    ) -> Option<&'r Route> {
+        if self.configs.is_empty() && self.concurrency.is_empty() {
+            return None;
+        }
+
+        match self.routes.get() {
+            Some(routes) => routes.limited_route(req),
+            None => self.route_of(req),
+        }
+    }
+
+    /// Whether a route has limits of its own.
+    fn is_limited(&self, route: &Route) -> bool {
+        route.name.as_deref().is_some_and(|name| {
+            lookup_route(&self.configs, name).is_some()
+                || lookup_route(&self.concurrency, name).is_some()
+        })
+    }
+
+    /// Takes the permits of the concurrency limits of a route.
+    ///
+    /// The permits are kept in the request-local cache until the
+    /// response is ready.
+    ///
+    async fn acquire_permits(
+        &self,
+        req: &Request<'_>,
+        route: Option<&Route>,
+    ) -> Option<RateLimitResponse> {
+        let limits = route
+            .and_then(|route| route.name.as_deref())
+            .and_then(|name| lookup_route(&self.concurrency, name))?;
+
+        let permits = req.local_cache(Permits::<K>::default);
+
+        for limit in limits {
+            match limit.acquire(req, |key| self.is_exempt(key)).await
+            {
+                Acquired::Skipped => {}
+                Acquired::Permit(permit) => {
+                    permits.0.lock().unwrap().push((self.id, permit));
+                }
+                Acquired::Rejected => {
+                    // Give back the permits taken so far.
+                    permits.release(self.id);
+
+                    let rejection = RateLimitResponse {
+                        retry_after: limit.retry_after(),
+                        exceeds_burst: false,
+                        vary: None,
+                        store_unavailable: false,
+                        status: None,
+                        decided_at: SystemTime::now(),
+                    };
+
+                    if let Some(on_limited) = &self.on_limited {
+                        on_limited(req, &rejection);
+                    }
+
+                    return Some(rejection);
+                }
+            }
+        }
+
+        None
+    }
+
+    fn release_permits(&self, req: &Request<'_>) {
+        if self.concurrency.is_empty() {
+            return;
+        }
+
+        req.local_cache(Permits::<K>::default).release(self.id);
+    }
+}
+
+#[rocket::async_trait]
+impl<K, S, C> Fairing for RateLimit<K, S, C>
+where
+    S: KeyedStateStore<K> + Send + Sync + 'static,
+    K: Eq + Clone + Hash + Send + Sync + 'static,
+    C: Clock + Send + Sync + 'static,
+{
+    fn info(&self) -> Info {
+        Info {
+            name: "Rate Limit",
+            kind: Kind::Ignite
+                | Kind::Liftoff
+                | Kind::Request
+                | Kind::Response,
+        }
+    }
+
+    async fn on_ignite(
+        &self,
+        rocket: Rocket<Build>,
+    ) -> fairing::Result {
+        let mut seen = HashSet::new();
+        let mut duplicates = false;
+
+        for route in rocket.routes() {
+            let Some(name) = route.name.as_deref() else {
+                // Only the global configs apply to the route, which
+                // is likely a mistake if routes have configs.
+                if !self.configs.is_empty()
+                    && self.defaults.is_empty()
+                {
+                    log::warn!(
+                        "route {} has no name, skipping route rate \
+                         limits",
+                        route.uri
+                    );
+                }
+
+                continue;
+            };
+
+            if self.is_limited(route) && !seen.insert(name) {
+                duplicates = true;
+
+                if self.strict_route_names {
+                    log::error!(
+                        "several rate limited routes are named `{}`, \
+                         e.g. {}",
+                        name,
+                        route.uri
+                    );
+                } else {
+                    log::warn!(
+                        "several rate limited routes are named `{}` \
+                         and share their limits, e.g. {}",
+                        name,
+                        route.uri
+                    );
+                }
+            }
+        }
+
+        if duplicates && self.strict_route_names {
+            log::error!(
+                "aborting, since strict route names are enabled and \
+                 several rate limited routes share a name"
+            );
+
+            return Err(rocket);
+        }
+
+        let at_internal_path = |route: &Route| {
+            let uri = route.uri.to_string();
+            let path = uri.split('?').next().unwrap_or_default();
+
+            path.ends_with(self.internal_path.as_str())
+        };
+
+        let colliding: Vec<String> = rocket
+            .routes()
+            .filter(|route| {
+                route.name.as_deref() != Some(DUMMY_ROUTE_NAME)
+                    && at_internal_path(route)
+            })
+            .map(|route| route.uri.to_string())
+            .collect();
+
+        for uri in &colliding {
+            log::error!(
+                "route {} collides with the internal path `{}` of \
+                 the rate limiter, pick another one with \
+                 `RateLimit::internal_path`",
+                uri,
+                self.internal_path
+            );
+        }
+
+        if !colliding.is_empty() {
+            return Err(rocket);
+        }
+
+        // Another rate limiter may have mounted the routes already.
+        let mounted = rocket.routes().any(|route| {
+            route.name.as_deref() == Some(DUMMY_ROUTE_NAME)
+                && at_internal_path(route)
+        });
+        if mounted {
+            return Ok(rocket);
+        }
+
+        let mut bases: BTreeSet<String> = rocket
+            .routes()
+            .map(|route| route.uri.base().to_string())
+            .collect();
+        bases.insert("/".to_string());
+
+        let rocket =
+            bases.into_iter().fold(rocket, |rocket, base| {
+                rocket.mount(base, dummy_routes(&self.internal_path))
+            });
+
+        Ok(rocket)
+    }
+
+    async fn on_liftoff(&self, rocket: &Rocket<Orbit>) {
+        let mut routes: Vec<_> = rocket.routes().collect();
+        routes.sort_by_key(|route| route.rank);
+        let _ = self.routes.set(RouteIndex::new(routes, |route| {
+            self.is_limited(route)
+        }));
+
+        let Some((interval, sweep)) = self.gc else {
+            return;
+        };
+
+        let mut limiters: Vec<Arc<Limiter<K, S, C>>> = Vec::new();
+        let mut tiered: Vec<Arc<TieredLimiter<K, S, C>>> = Vec::new();
+
+        for cfg in self
+            .configs
+            .values()
+            .flatten()
+            .chain(&self.global)
+            .chain(&self.defaults)
+        {
+            // Shared configs appear once per route.
+            match &cfg.limiter {
+                ConfigLimiter::Keyed(limiter) => {
+                    if !limiters
+                        .iter()
+                        .any(|other| Arc::ptr_eq(other, limiter))
+                    {
+                        limiters.push(limiter.clone());
+                    }
+                }
+                ConfigLimiter::Tiered(tiers) => {
+                    if !tiered
+                        .iter()
+                        .any(|other| Arc::ptr_eq(other, tiers))
+                    {
+                        tiered.push(tiers.clone());
+                    }
+                }
+                ConfigLimiter::Layered(layers) => {
+                    for limiter in layers.iter() {
+                        if !limiters
+                            .iter()
+                            .any(|other| Arc::ptr_eq(other, limiter))
+                        {
+                            limiters.push(limiter.clone());
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        rocket::tokio::spawn(async move {
+            let mut interval =
+                rocket::tokio::time::interval(interval);
+
+            loop {
+                interval.tick().await;
+
+                for limiter in &limiters {
+                    sweep(limiter);
+                }
+
+                // Tiered configs create limiters as they go.
+                for tiers in &tiered {
+                    tiers.sweep(sweep);
+                }
+            }
+        });
+    }
+
+    async fn on_request(
+        &self,
+        req: &mut Request<'_>,
+        // Never read or peek the data, even when the request is
+        // redirected, since handlers expect the complete body.
+        _data: &mut Data<'_>,
+    ) {
+        // Skip checks while disabled, or if another rate limiter
+        // already rejected the request.
+        if !self.is_enabled() || original_uri(req).is_some() {
+            return;
+        }
+
+        // Rocket routes HEAD requests without a HEAD route to the GET
+        // route, so they have to count against its limits too.
+        let head_as_get = req.method() == Method::Head
+            && self.route_of(req).is_none();
+        if head_as_get {
+            req.set_method(Method::Get);
+        }
+
+        let route = self.matched_route(req);
+
+        let mut commits = Vec::new();
+        let (mut result, mut violator) =
+            self.check_rate_limit(req, route, &mut commits).await;
+
+        if let RateLimitMode::Delay { max_delay } = self.mode {
+            let mut delayed = Duration::ZERO;
+
+            while let Some(rejection) = result.rejection {
+                if rejection.exceeds_burst
+                    || rejection.store_unavailable
+                    || delayed + rejection.retry_after > max_delay
+                {
+                    break;
+                }
+
+                rocket::tokio::time::sleep(rejection.retry_after)
+                    .await;
+                delayed += rejection.retry_after;
+
+                commits.clear();
+                (result, violator) = self
+                    .check_rate_limit(req, route, &mut commits)
+                    .await;
+            }
+        }
+
+        // Only the final rejection counts as a violation, not the
+        // checks that were waited out.
+        self.report(req, &result, violator.as_ref());
+
+        if result.rejection.is_none() {
+            result.rejection = self.acquire_permits(req, route).await;
+        }
+
+        // Resolve the status of the rejection, so the guard fails with
+        // the status the fairing responds with.
+        if let Some(rejection) = &mut result.rejection {
+            rejection.status = Some(self.status_of(rejection));
+        }
+
+        let counter = match result.rejection {
+            Some(_) => &self.denied,
+            None => &self.allowed,
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+
+        // Rejected requests are never counted by deferred configs.
+        if result.rejection.is_none() && !commits.is_empty() {
+            req.local_cache(PendingCommits::default)
+                .0
+                .lock()
+                .unwrap()
+                .extend(commits);
+        }
+
+        let redirect_base = match result.rejection {
+            Some(rejection) if self.redirect => {
+                // Rejections of global and default configs are
+                // checked without looking up the route.
+                let base = route
+                    .or_else(|| self.route_of(req))
+                    .map_or("/".to_string(), |route| {
+                        route.uri.base().to_string()
+                    });
+
+                Some((base, self.status_of(&rejection)))
+            }
+            _ => None,
+        };
+
+        if head_as_get {
+            req.set_method(Method::Head);
+        }
+
+        if let Some((base, status)) = redirect_base {
+            let path = dummy_path(&base, &self.internal_path);
+
+            // Keep the query, so later fairings and logs still see it.
+            let uri = match req.uri().query() {
+                Some(query) => format!("{}?{}", path, query.as_str()),
+                None => path,
+            };
+            let uri =
+                Origin::parse_owned(uri).expect("valid redirect uri");
+
+            let original = req.uri().clone().into_owned();
+            req.local_cache(|| OriginalUri(Some(original)));
+            req.local_cache(|| {
+                CatcherStatus(self.catchers.then_some(status))
+            });
+
+            req.set_uri(uri);
+        }
+
+        if result.state.is_some() || result.rejection.is_some() {
+            req.local_cache(Decisions::default)
+                .0
+                .lock()
+                .unwrap()
+                .push((self.id, result));
+        }
+    }
+
+    async fn on_response<'r>(
+        &self,
+        req: &'r Request<'_>,
+        res: &mut Response<'r>,
+    ) {
+        self.release_permits(req);
+
+        let commits = std::mem::take(
+            &mut *req
+                .local_cache(PendingCommits::default)
+                .0
+                .lock()
+                .unwrap(),
+        );
+        for commit in commits {
+            commit(res.status()).await;
+        }
+
+        let result = decisions(req)
+            .into_iter()
+            .find(|(id, _)| *id == self.id)
+            .map(|(_, result)| result)
+            .unwrap_or_default();
+
+        // Computed once, so the jittered wait of `Retry-After` and
+        // the reset of the headers agree.
+        let rejection =
+            result.rejection.as_ref().map(|r| self.advertised(r));
+
+        let redirected = self.redirect
+            && req
+                .uri()
+                .path()
+                .ends_with(self.internal_path.as_str());
+
+        if !self.redirect || redirected {
+            if let Some(rate_limit) = &rejection {
+                // Without redirects, only the failing guard invokes
+                // the catcher, other responses are replaced.
+                let caught = redirected
+                    || res.status() == self.status_of(rate_limit);
+
+                if self.catchers && caught {
+                    // The catcher already rendered the response.
+                    self.apply_retry_after(res, rate_limit);
+                    apply_vary(res, rate_limit);
+                } else {
+                    cancel_upgrade(req, res);
+                    self.apply_rate_limit(res, rate_limit);
+                }
+            }
+        }
+
+        if self.headers {
+            if let Some(state) = &result.state {
+                let mut state = *state;
+
+                if let Some(rate_limit) = &rejection {
+                    if !rate_limit.exceeds_burst {
+                        state.reset = rate_limit.retry_after;
+                    }
+                }
+
+                self.apply_headers(res, &state);
+            }
         }
     }
 }
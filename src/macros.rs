@@ -0,0 +1,47 @@
+//! Limits declared on the handlers they protect.
+//!
+//! Requires the `macros` feature. `#[rate_limit]` attaches a limit
+//! to a handler by its route name, so renaming the handler can't
+//! detach it, and `rate_limits!` collects the limits of handlers
+//! into a [RateLimit](crate::RateLimit), like `routes!` does for
+//! routes:
+//!
+//! ```no_run
+//!# use rocket::{get, routes};
+//! use rocket_rate_limit::macros::{rate_limit, rate_limits};
+//! use rocket_rate_limit::HeaderKeyFilter;
+//!
+//! #[rate_limit(quota = "10/min", by = ip)]
+//! #[get("/search")]
+//! fn search() -> &'static str {
+//!     "results"
+//! }
+//!
+//! #[rate_limit(quota = "100/15m", by = HeaderKeyFilter::new("x-api-key"))]
+//! #[rate_limit(quota = "5/s", by = ip, priority = 1)]
+//! #[get("/export")]
+//! fn export() -> &'static str {
+//!     "data"
+//! }
+//!
+//! let rocket = rocket::build()
+//!     .mount("/", routes![search, export])
+//!     .attach(rate_limits![search, export]);
+//! ```
+//!
+//! Quotas allow `burst/period` requests, where the period is a number
+//! of `ms`, `s`, `m`, `h` or `d` (or `sec`, `min`, `hour` and `day`),
+//! like [QuotaSpec](crate::QuotaSpec) takes, and are checked at
+//! compile time. `by = ip` is short for
+//! [IpKeyFilter](crate::IpKeyFilter), any other filter is written as
+//! an expression. Handlers may have several limits, with the same
+//! meaning as several configs of a route. The attributes must be
+//! written as `rate_limit` or a suffix of its full path, not renamed
+//! on import.
+//!
+//! The limits use the default keyed store and clock with `String`
+//! keys. `rate_limits!` returns a rate limiter with just these
+//! limits, which can be configured further like any other.
+//!
+
+pub use rocket_rate_limit_codegen::{rate_limit, rate_limits};
@@ -0,0 +1,192 @@
+use governor::clock::{Clock, DefaultClock, Reference};
+use governor::nanos::Nanos;
+use governor::state::keyed::ShrinkableKeyedStateStore;
+use governor::state::StateStore;
+use rocket::serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// An in-memory state store whose state can be saved and restored,
+/// e.g. across restarts.
+///
+/// Without it, redeploying gives every client a full burst again.
+/// Clones refer to the same state, so keep one to take snapshots:
+///
+/// ```no_run
+///# use governor::{clock::DefaultClock, Quota, RateLimiter};
+///# use rocket_rate_limit::{IpKeyFilter, PersistentStore, RateLimitConfig};
+///# use std::num::NonZeroU32;
+///# let saved = None;
+/// let clock = DefaultClock::default();
+/// let store = PersistentStore::<String>::new(&clock);
+/// let limiter = RateLimiter::new(
+///     Quota::per_minute(NonZeroU32::new(5).unwrap()),
+///     store.clone(),
+///     &clock,
+/// );
+///
+/// if let Some(snapshot) = saved {
+///     store.restore(snapshot);
+/// }
+///
+/// let config = RateLimitConfig::new(limiter, Box::new(IpKeyFilter));
+///
+/// // Before shutting down:
+/// let snapshot = store.snapshot();
+/// ```
+///
+/// Governor measures time from the creation of the limiter, which
+/// the store can't see, so the store has to be created right before
+/// the limiter, with the same clock. Restored keys are accurate to
+/// a millisecond plus the time between the two, and the time the
+/// application was down counts as passed.
+///
+pub struct PersistentStore<K, C = DefaultClock>(Arc<Inner<K, C>>)
+where
+    C: Clock;
+
+struct Inner<K, C: Clock> {
+    states: Mutex<HashMap<K, Nanos>>,
+    clock: C,
+    /// Approximately the start of the limiter.
+    start: C::Instant,
+}
+
+/// Saved state of a [PersistentStore].
+///
+/// Serializable with the `rocket::serde` formats, e.g. JSON.
+///
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct StoreSnapshot<K> {
+    /// Milliseconds since the Unix epoch.
+    taken_at: u64,
+    /// Milliseconds until the bucket of each key is full again.
+    keys: Vec<(K, u64)>,
+}
+
+impl<K, C> PersistentStore<K, C>
+where
+    K: Eq + Hash + Clone,
+    C: Clock,
+{
+    pub fn new(clock: &C) -> Self {
+        PersistentStore(Arc::new(Inner {
+            states: Mutex::new(HashMap::new()),
+            clock: clock.clone(),
+            start: clock.now(),
+        }))
+    }
+
+    /// Saves the keys whose bucket isn't full.
+    pub fn snapshot(&self) -> StoreSnapshot<K> {
+        let elapsed = self.elapsed();
+
+        let keys = self
+            .0
+            .states
+            .lock()
+            .unwrap()
+            .iter()
+            .filter_map(|(key, &tat)| {
+                let until_full =
+                    Duration::from(tat).saturating_sub(elapsed);
+
+                (!until_full.is_zero()).then(|| {
+                    (key.clone(), until_full.as_millis() as u64)
+                })
+            })
+            .collect();
+
+        StoreSnapshot {
+            taken_at: unix_millis(),
+            keys,
+        }
+    }
+
+    /// Loads the keys of a snapshot, replacing their current state.
+    pub fn restore(&self, snapshot: StoreSnapshot<K>) {
+        let downtime =
+            unix_millis().saturating_sub(snapshot.taken_at);
+        let elapsed = self.elapsed();
+        let mut states = self.0.states.lock().unwrap();
+
+        for (key, until_full) in snapshot.keys {
+            let Some(left) = until_full.checked_sub(downtime) else {
+                continue;
+            };
+
+            let tat = elapsed + Duration::from_millis(left);
+            states.insert(key, Nanos::from(tat));
+        }
+    }
+
+    /// Time since the start of the limiter, by its clock.
+    fn elapsed(&self) -> Duration {
+        self.0.clock.now().duration_since(self.0.start).into()
+    }
+}
+
+fn unix_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64
+}
+
+impl<K, C: Clock> Clone for PersistentStore<K, C> {
+    fn clone(&self) -> Self {
+        PersistentStore(self.0.clone())
+    }
+}
+
+impl<K, C> StateStore for PersistentStore<K, C>
+where
+    K: Eq + Hash + Clone,
+    C: Clock,
+{
+    type Key = K;
+
+    fn measure_and_replace<T, F, E>(
+        &self,
+        key: &Self::Key,
+        f: F,
+    ) -> Result<T, E>
+    where
+        F: Fn(Option<Nanos>) -> Result<(T, Nanos), E>,
+    {
+        let mut states = self.0.states.lock().unwrap();
+        let (result, tat) = f(states.get(key).copied())?;
+
+        states.insert(key.clone(), tat);
+        Ok(result)
+    }
+}
+
+impl<K, C> ShrinkableKeyedStateStore<K> for PersistentStore<K, C>
+where
+    K: Eq + Hash + Clone,
+    C: Clock,
+{
+    fn retain_recent(&self, drop_below: Nanos) {
+        self.0
+            .states
+            .lock()
+            .unwrap()
+            .retain(|_, tat| *tat > drop_below);
+    }
+
+    fn shrink_to_fit(&self) {
+        self.0.states.lock().unwrap().shrink_to_fit();
+    }
+
+    fn len(&self) -> usize {
+        self.0.states.lock().unwrap().len()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.0.states.lock().unwrap().is_empty()
+    }
+}
@@ -0,0 +1,49 @@
+//! The grammar of quotas like `10/min` or `100/15m`.
+//!
+//! The codegen crate includes this file for `#[rate_limit]`, so it
+//! must not use anything else of the crate.
+
+/// Parses `value` into the burst and the period in milliseconds.
+pub(crate) fn parse(value: &str) -> Result<(u32, u64), &'static str> {
+    let invalid = "expected a quota like `10/min` or `100/15m`";
+
+    let (burst, per) = value.split_once('/').ok_or(invalid)?;
+    let burst: u32 = burst.trim().parse().map_err(|_| invalid)?;
+
+    let per = per.trim();
+    let (count, unit) = per.split_at(
+        per.find(|c: char| !c.is_ascii_digit()).unwrap_or(per.len()),
+    );
+    let count: u64 = match count {
+        "" => 1,
+        count => count.parse().map_err(|_| invalid)?,
+    };
+    let unit = match unit.trim() {
+        "ms" => 1,
+        "s" | "sec" | "second" => 1000,
+        "m" | "min" | "minute" => 60 * 1000,
+        "h" | "hour" => 60 * 60 * 1000,
+        "d" | "day" => 24 * 60 * 60 * 1000,
+        _ => return Err(invalid),
+    };
+
+    if burst == 0 || count == 0 {
+        return Err(
+            "quotas must allow at least one request per period",
+        );
+    }
+
+    let per = count
+        .checked_mul(unit)
+        .ok_or("the period of the quota is too long")?;
+
+    // Governor replenishes one request per `per / burst`, which
+    // can't be shorter than a nanosecond.
+    if u128::from(per) * 1_000_000 < u128::from(burst) {
+        return Err(
+            "the period must be at least `burst` nanoseconds",
+        );
+    }
+
+    Ok((burst, per))
+}
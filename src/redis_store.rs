@@ -0,0 +1,198 @@
+use crate::report_store_error;
+use governor::nanos::Nanos;
+use governor::state::StateStore;
+use r2d2::{Pool, PooledConnection};
+use redis::{Client, RedisResult, Script};
+use std::fmt::Display;
+use std::marker::PhantomData;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Atomically replaces a key if it still holds the expected value.
+///
+/// An empty expected value means that the key must not exist.
+///
+const COMPARE_AND_SET: &str = r#"
+local current = redis.call('GET', KEYS[1])
+if (current == false and ARGV[1] == '') or current == ARGV[1] then
+    redis.call('SET', KEYS[1], ARGV[2], 'PX', ARGV[3])
+    return 1
+end
+return 0
+"#;
+
+/// How often a compare-and-set is retried if another instance changed
+/// the key in the meantime, before the store is treated as
+/// unavailable. Keeps checks of heavily contended keys from spinning
+/// forever.
+///
+const MAX_ATTEMPTS: usize = 8;
+
+/// A keyed state store that keeps the rate limiter state in Redis.
+///
+/// This allows several instances of an application to share their
+/// rate limits. Requires the `redis` feature.
+///
+/// ```no_run
+///# use governor::{clock::DefaultClock, Quota, RateLimiter};
+///# use rocket_rate_limit::RedisKeyedStore;
+///# use std::num::NonZeroU32;
+/// let client = redis::Client::open("redis://127.0.0.1/").unwrap();
+/// let pool = r2d2::Pool::new(client).unwrap();
+///
+/// let limiter = RateLimiter::new(
+///     Quota::per_second(NonZeroU32::new(10).unwrap()),
+///     RedisKeyedStore::<String>::new(pool, "rate-limit"),
+///     &DefaultClock::default(),
+/// );
+/// ```
+///
+/// # Tradeoffs
+///
+/// Governor's state stores are synchronous, so every check blocks
+/// the current thread on a round trip to Redis. Keep the pool
+/// close to the application and configure short timeouts.
+///
+/// Governor measures time relative to when the limiter was created,
+/// which differs between instances. The store converts the state
+/// to wall-clock time (captured when the store is created), so
+/// create the limiter right after the store, and keep the clocks of
+/// all instances in sync.
+///
+/// If Redis can't be reached, nothing is stored and the error is
+/// reported with [report_store_error], so the request is handled as
+/// [RateLimit::on_store_error] says.
+///
+/// [RateLimit::on_store_error]: crate::RateLimit::on_store_error
+///
+pub struct RedisKeyedStore<K> {
+    pool: Pool<Client>,
+    prefix: String,
+    epoch: u64,
+    script: Script,
+    _key: PhantomData<fn(K)>,
+}
+
+impl<K> RedisKeyedStore<K> {
+    /// Creates a store that keeps its keys under `prefix`.
+    pub fn new<P: Into<String>>(
+        pool: Pool<Client>,
+        prefix: P,
+    ) -> Self {
+        RedisKeyedStore {
+            pool,
+            prefix: prefix.into(),
+            epoch: wall_clock_nanos(),
+            script: Script::new(COMPARE_AND_SET),
+            _key: PhantomData,
+        }
+    }
+}
+
+impl<K: Display> RedisKeyedStore<K> {
+    fn redis_key(&self, key: &K) -> String {
+        format!("{}:{}", self.prefix, key)
+    }
+
+    /// Runs a single compare-and-set round.
+    ///
+    /// Returns `None` if the key was changed concurrently.
+    ///
+    fn try_measure_and_replace<T, F, E>(
+        &self,
+        conn: &mut PooledConnection<Client>,
+        key: &str,
+        f: &F,
+    ) -> RedisResult<Option<Result<T, E>>>
+    where
+        F: Fn(Option<Nanos>) -> Result<(T, Nanos), E>,
+    {
+        let current: Option<u64> =
+            redis::cmd("GET").arg(key).query(&mut **conn)?;
+
+        let prev = current.map(|absolute| {
+            Nanos::from(absolute.saturating_sub(self.epoch))
+        });
+
+        let (result, next) = match f(prev) {
+            Ok(decision) => decision,
+            Err(err) => return Ok(Some(Err(err))),
+        };
+
+        let next = self.epoch + next.as_u64();
+
+        // The state is irrelevant once its arrival time has passed.
+        let ttl_ms =
+            next.saturating_sub(wall_clock_nanos()) / 1_000_000 + 1;
+
+        let expected = current
+            .map(|value| value.to_string())
+            .unwrap_or_default();
+
+        let replaced: i32 = self
+            .script
+            .key(key)
+            .arg(expected)
+            .arg(next)
+            .arg(ttl_ms)
+            .invoke(&mut **conn)?;
+
+        Ok((replaced == 1).then_some(Ok(result)))
+    }
+}
+
+impl<K: Display> StateStore for RedisKeyedStore<K> {
+    type Key = K;
+
+    fn measure_and_replace<T, F, E>(
+        &self,
+        key: &Self::Key,
+        f: F,
+    ) -> Result<T, E>
+    where
+        F: Fn(Option<Nanos>) -> Result<(T, Nanos), E>,
+    {
+        let key = self.redis_key(key);
+
+        let mut conn = match self.pool.get() {
+            Ok(conn) => conn,
+            Err(error) => return unavailable(error, f),
+        };
+
+        for _ in 0..MAX_ATTEMPTS {
+            match self.try_measure_and_replace(&mut conn, &key, &f) {
+                Ok(Some(result)) => return result,
+                Ok(None) => continue,
+                Err(error) => return unavailable(error, f),
+            }
+        }
+
+        unavailable(
+            format_args!(
+                "key `{}` kept changing after {} attempts",
+                key, MAX_ATTEMPTS
+            ),
+            f,
+        )
+    }
+}
+
+/// Reports `error` and answers the check with an empty state.
+fn unavailable<T, F, E>(error: impl Display, f: F) -> Result<T, E>
+where
+    F: Fn(Option<Nanos>) -> Result<(T, Nanos), E>,
+{
+    log::error!("failed to reach redis: {}", error);
+
+    #[cfg(feature = "tracing")]
+    tracing::error!(%error, "failed to reach redis");
+
+    report_store_error();
+    f(None).map(|(result, _)| result)
+}
+
+fn wall_clock_nanos() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|since_epoch| since_epoch.as_nanos() as u64)
+        .unwrap_or_default()
+}
@@ -0,0 +1,161 @@
+use rocket::http::Method;
+use rocket::{Request, Route};
+use std::collections::HashMap;
+
+/// The routes of an application, grouped by method and by the first
+/// segment of their path.
+///
+/// A request can only match routes of its method whose first
+/// segment is the same as its own, or dynamic, so only those are
+/// looked at. Most requests of large applications then never look
+/// at a route at all, since none of their candidates is limited.
+///
+pub(crate) struct RouteIndex {
+    methods: HashMap<Method, MethodRoutes>,
+}
+
+/// The routes of a method.
+#[derive(Default)]
+struct MethodRoutes {
+    /// Routes starting with a static segment, by the segment.
+    by_segment: HashMap<String, Candidates>,
+    /// Routes of `/`.
+    root: Candidates,
+    /// Routes starting with a dynamic segment, for requests whose
+    /// first segment no static route starts with.
+    dynamic: Candidates,
+}
+
+/// Routes a request may match, in the order Rocket tries them.
+///
+/// Every group also holds the routes starting with a dynamic
+/// segment.
+///
+#[derive(Default)]
+struct Candidates {
+    routes: Vec<Route>,
+    /// Whether any of the routes has limits of its own.
+    limited: bool,
+}
+
+impl Candidates {
+    fn push(&mut self, route: &Route, limited: bool) {
+        self.routes.push(route.clone());
+        self.limited |= limited;
+    }
+
+    fn find(&self, req: &Request<'_>) -> Option<&Route> {
+        self.routes.iter().find(|route| route.matches(req))
+    }
+}
+
+/// The first segment of the path of a route.
+enum FirstSegment<'a> {
+    Root,
+    Static(&'a str),
+    Dynamic,
+}
+
+fn first_segment(route: &Route) -> FirstSegment<'_> {
+    match route.uri.path().trim_start_matches('/').split('/').next() {
+        None | Some("") => FirstSegment::Root,
+        // Requests are matched by their decoded segments, so encoded
+        // segments are tried for every request.
+        Some(segment)
+            if segment.starts_with('<') || segment.contains('%') =>
+        {
+            FirstSegment::Dynamic
+        }
+        Some(segment) => FirstSegment::Static(segment),
+    }
+}
+
+impl RouteIndex {
+    /// Groups `routes`, which have to be sorted by rank.
+    ///
+    /// `limited` tells whether a route has limits of its own.
+    ///
+    pub(crate) fn new<'a, I, F>(routes: I, limited: F) -> Self
+    where
+        I: IntoIterator<Item = &'a Route>,
+        I::IntoIter: Clone,
+        F: Fn(&Route) -> bool,
+    {
+        let routes = routes.into_iter();
+        let mut methods: HashMap<Method, MethodRoutes> =
+            HashMap::new();
+
+        // Create the groups first, so routes starting with a dynamic
+        // segment are added to all of them in order.
+        for route in routes.clone() {
+            if let FirstSegment::Static(segment) =
+                first_segment(route)
+            {
+                methods
+                    .entry(route.method)
+                    .or_default()
+                    .by_segment
+                    .entry(segment.to_string())
+                    .or_default();
+            }
+        }
+
+        for route in routes {
+            let limited = limited(route);
+            let method = methods.entry(route.method).or_default();
+
+            match first_segment(route) {
+                FirstSegment::Root => {
+                    method.root.push(route, limited)
+                }
+                FirstSegment::Static(segment) => method
+                    .by_segment
+                    .get_mut(segment)
+                    .expect("groups are created up front")
+                    .push(route, limited),
+                FirstSegment::Dynamic => {
+                    for candidates in method.by_segment.values_mut() {
+                        candidates.push(route, limited);
+                    }
+
+                    method.root.push(route, limited);
+                    method.dynamic.push(route, limited);
+                }
+            }
+        }
+
+        RouteIndex { methods }
+    }
+
+    fn candidates(&self, req: &Request<'_>) -> Option<&Candidates> {
+        let method = self.methods.get(&req.method())?;
+
+        Some(match req.uri().path().segments().next() {
+            Some(segment) => method
+                .by_segment
+                .get(segment)
+                .unwrap_or(&method.dynamic),
+            None => &method.root,
+        })
+    }
+
+    /// Finds the route Rocket will route `req` to.
+    pub(crate) fn route(&self, req: &Request<'_>) -> Option<&Route> {
+        self.candidates(req)?.find(req)
+    }
+
+    /// Like [RouteIndex::route], but returns `None` without matching
+    /// any route if none of the candidates has limits of its own.
+    pub(crate) fn limited_route(
+        &self,
+        req: &Request<'_>,
+    ) -> Option<&Route> {
+        let candidates = self.candidates(req)?;
+
+        if !candidates.limited {
+            return None;
+        }
+
+        candidates.find(req)
+    }
+}
@@ -0,0 +1,103 @@
+use governor::nanos::Nanos;
+use governor::state::keyed::ShrinkableKeyedStateStore;
+use governor::state::StateStore;
+use std::convert::Infallible;
+use std::hash::Hash;
+use std::sync::Arc;
+
+/// A state store that can be shared between its limiter and the
+/// code that resets keys.
+///
+/// Clones refer to the same state. Pass one clone to the limiter,
+/// and another one to [RateLimitConfig::resettable], so
+/// [RateLimit::reset] can clear keys:
+///
+/// ```no_run
+///# use governor::{clock::DefaultClock, Quota, RateLimiter};
+///# use governor::state::keyed::DefaultKeyedStateStore;
+///# use rocket_rate_limit::{IpKeyFilter, RateLimitConfig, SharedStore};
+///# use std::num::NonZeroU32;
+/// let store = SharedStore::new(DefaultKeyedStateStore::<String>::default());
+///
+/// let config = RateLimitConfig::new(
+///     RateLimiter::new(
+///         Quota::per_minute(NonZeroU32::new(5).unwrap()),
+///         store.clone(),
+///         &DefaultClock::default(),
+///     ),
+///     Box::new(IpKeyFilter),
+/// )
+/// .resettable(&store);
+/// ```
+///
+/// Any store can be wrapped, since resetting only replaces the state
+/// of a key with one that allows a full burst.
+///
+/// [RateLimitConfig::resettable]: crate::RateLimitConfig::resettable
+/// [RateLimit::reset]: crate::RateLimit::reset
+///
+pub struct SharedStore<S>(Arc<S>);
+
+impl<S> SharedStore<S> {
+    pub fn new(store: S) -> Self {
+        SharedStore(Arc::new(store))
+    }
+}
+
+impl<S: StateStore> SharedStore<S> {
+    /// Gives `key` its full burst back.
+    pub fn reset(&self, key: &S::Key) {
+        let _ = self.0.measure_and_replace(key, |_| {
+            Ok::<_, Infallible>(((), Nanos::from(0)))
+        });
+    }
+}
+
+impl<S> Clone for SharedStore<S> {
+    fn clone(&self) -> Self {
+        SharedStore(self.0.clone())
+    }
+}
+
+impl<S: Default> Default for SharedStore<S> {
+    fn default() -> Self {
+        SharedStore::new(S::default())
+    }
+}
+
+impl<S: StateStore> StateStore for SharedStore<S> {
+    type Key = S::Key;
+
+    fn measure_and_replace<T, F, E>(
+        &self,
+        key: &Self::Key,
+        f: F,
+    ) -> Result<T, E>
+    where
+        F: Fn(Option<Nanos>) -> Result<(T, Nanos), E>,
+    {
+        self.0.measure_and_replace(key, f)
+    }
+}
+
+impl<K, S> ShrinkableKeyedStateStore<K> for SharedStore<S>
+where
+    K: Hash,
+    S: ShrinkableKeyedStateStore<K>,
+{
+    fn retain_recent(&self, drop_below: Nanos) {
+        self.0.retain_recent(drop_below)
+    }
+
+    fn shrink_to_fit(&self) {
+        self.0.shrink_to_fit()
+    }
+
+    fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
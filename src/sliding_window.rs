@@ -0,0 +1,152 @@
+use crate::{AsyncKeyedStore, RateLimitStatus, StoreOutcome};
+use governor::clock::{Clock, DefaultClock, Reference};
+use std::collections::{HashMap, VecDeque};
+use std::hash::Hash;
+use std::num::NonZeroU32;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Keys are swept once the log has at least this many.
+const MIN_SWEEP_AT: usize = 1024;
+
+/// A store that allows at most `limit` requests in any `window`.
+///
+/// Governor's GCRA replenishes the quota continuously, so a client
+/// that waits part of the period gets part of the burst back. The
+/// log keeps the time of every counted request instead, and only
+/// frees a slot once the request that used it is older than the
+/// window. Use it with [RateLimitConfig::sliding_window], or
+/// [RateLimitConfig::sliding_window_with_clock] for another clock:
+///
+/// ```no_run
+///# use rocket_rate_limit::{IpKeyFilter, RateLimitConfig};
+///# use governor::state::keyed::DefaultKeyedStateStore;
+///# use std::num::NonZeroU32;
+///# use std::time::Duration;
+/// let config: RateLimitConfig<String, DefaultKeyedStateStore<String>> =
+///     RateLimitConfig::sliding_window(
+///         NonZeroU32::new(100).unwrap(),
+///         Duration::from_secs(60),
+///         Box::new(IpKeyFilter),
+///     );
+/// ```
+///
+/// # Tradeoffs
+///
+/// GCRA stores a single timestamp per key, while the log stores up
+/// to `limit` of them, so large limits take a lot more memory. Keys
+/// without requests in the window are removed whenever the number
+/// of keys has doubled since the last sweep.
+///
+/// [RateLimitConfig::sliding_window]: crate::RateLimitConfig::sliding_window
+/// [RateLimitConfig::sliding_window_with_clock]: crate::RateLimitConfig::sliding_window_with_clock
+///
+pub struct SlidingWindowLog<K, C = DefaultClock>
+where
+    C: Clock,
+{
+    limit: NonZeroU32,
+    window: Duration,
+    clock: C,
+    log: Mutex<Log<K, C::Instant>>,
+}
+
+struct Log<K, I> {
+    /// Times of the counted requests of each key, oldest first.
+    keys: HashMap<K, VecDeque<I>>,
+    /// Number of keys at which stale keys are removed next.
+    sweep_at: usize,
+}
+
+impl<K, C> SlidingWindowLog<K, C>
+where
+    K: Eq + Hash + Clone,
+    C: Clock,
+{
+    pub fn new(
+        limit: NonZeroU32,
+        window: Duration,
+        clock: &C,
+    ) -> Self {
+        SlidingWindowLog {
+            limit,
+            window,
+            clock: clock.clone(),
+            log: Mutex::new(Log {
+                keys: HashMap::new(),
+                sweep_at: MIN_SWEEP_AT,
+            }),
+        }
+    }
+
+    fn status(
+        &self,
+        remaining: u32,
+        reset: Duration,
+    ) -> RateLimitStatus {
+        RateLimitStatus::new(
+            self.limit.get(),
+            remaining,
+            reset,
+            self.window,
+        )
+    }
+}
+
+#[rocket::async_trait]
+impl<K, C> AsyncKeyedStore<K> for SlidingWindowLog<K, C>
+where
+    K: Eq + Hash + Clone + Send + Sync,
+    C: Clock + Send + Sync,
+{
+    async fn check(&self, key: &K, cost: NonZeroU32) -> StoreOutcome {
+        if cost > self.limit {
+            return StoreOutcome::ExceedsBurst {
+                burst_size: self.limit.get(),
+            };
+        }
+
+        let now = self.clock.now();
+        let window = self.window;
+        let age = |time: C::Instant| {
+            Duration::from(now.duration_since(time))
+        };
+        let mut log = self.log.lock().unwrap();
+
+        if !log.keys.contains_key(key)
+            && log.keys.len() >= log.sweep_at
+        {
+            log.keys.retain(|_, times| {
+                times.back().is_some_and(|&time| age(time) < window)
+            });
+            log.sweep_at = (log.keys.len() * 2).max(MIN_SWEEP_AT);
+        }
+
+        let times = log.keys.entry(key.clone()).or_default();
+
+        while times.front().is_some_and(|&time| age(time) >= window) {
+            times.pop_front();
+        }
+
+        let used = times.len() as u32;
+        let needed = cost.get();
+        let limit = self.limit.get();
+
+        if used + needed > limit {
+            // The request fits once enough of the oldest requests
+            // left the window.
+            let freeing = times[(used + needed - limit - 1) as usize];
+            let wait = window.saturating_sub(age(freeing));
+
+            return StoreOutcome::Limited(
+                self.status(limit - used, wait),
+            );
+        }
+
+        times.extend((0..needed).map(|_| now));
+
+        StoreOutcome::Allowed(
+            self.status(limit - used - needed, window),
+        )
+    }
+}
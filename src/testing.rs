@@ -0,0 +1,152 @@
+//! Helpers for testing rate limits with Rocket's local client.
+//!
+//! Requires the `testing` feature, e.g. as a dev-dependency:
+//!
+//! ```no_run
+//!# use governor::Quota;
+//!# use governor::state::keyed::DefaultKeyedStateStore;
+//!# use rocket::{get, routes};
+//!# use rocket::local::blocking::Client;
+//!# use rocket_rate_limit::{ConstantKeyFilter, RateLimitConfig};
+//!# use rocket_rate_limit::testing::{assert_limited, fake_limiter, with_fake_clock};
+//!# use std::num::NonZeroU32;
+//!# use std::time::Duration;
+//! #[get("/search")]
+//! fn search() -> &'static str {
+//!     "results"
+//! }
+//!
+//! let (mut rate_limit, clock) =
+//!     with_fake_clock::<String, DefaultKeyedStateStore<String>>();
+//!
+//! rate_limit.add("search", vec![RateLimitConfig::new(
+//!     fake_limiter(Quota::per_minute(NonZeroU32::new(1).unwrap()), &clock),
+//!     Box::new(ConstantKeyFilter::new("everyone".to_string())),
+//! )]);
+//!
+//! let rocket = rocket::build()
+//!     .mount("/", routes![search])
+//!     .attach(rate_limit);
+//! let client = Client::tracked(rocket).unwrap();
+//! client.get("/search").dispatch();
+//! assert_limited(&client.get("/search").dispatch());
+//!
+//! clock.advance(Duration::from_secs(60));
+//! ```
+//!
+
+use crate::RateLimit;
+use governor::clock::FakeRelativeClock;
+use governor::state::keyed::{
+    DefaultKeyedStateStore, KeyedStateStore,
+};
+use governor::{Quota, RateLimiter};
+use rocket::http::{HeaderMap, Status};
+use rocket::local::{asynchronous, blocking};
+use std::collections::HashMap;
+use std::hash::Hash;
+
+/// A response of Rocket's blocking or asynchronous local client.
+pub trait TestResponse {
+    fn status(&self) -> Status;
+    fn headers(&self) -> &HeaderMap<'_>;
+}
+
+impl TestResponse for blocking::LocalResponse<'_> {
+    fn status(&self) -> Status {
+        blocking::LocalResponse::status(self)
+    }
+
+    fn headers(&self) -> &HeaderMap<'_> {
+        blocking::LocalResponse::headers(self)
+    }
+}
+
+impl TestResponse for asynchronous::LocalResponse<'_> {
+    fn status(&self) -> Status {
+        asynchronous::LocalResponse::status(self)
+    }
+
+    fn headers(&self) -> &HeaderMap<'_> {
+        asynchronous::LocalResponse::headers(self)
+    }
+}
+
+/// Asserts that a response was rejected with `429 Too Many Requests`
+/// and a `Retry-After` header.
+///
+/// Rate limiters that use another
+/// [reject_status](RateLimit::reject_status) can use
+/// [assert_limited_with].
+///
+pub fn assert_limited<R: TestResponse>(response: &R) {
+    assert_limited_with(response, Status::TooManyRequests);
+}
+
+/// Asserts that a response was rejected with `status` and a
+/// `Retry-After` header.
+pub fn assert_limited_with<R: TestResponse>(
+    response: &R,
+    status: Status,
+) {
+    assert_eq!(
+        response.status(),
+        status,
+        "the request wasn't rate limited"
+    );
+    assert!(
+        response.headers().contains("Retry-After"),
+        "the rejection has no Retry-After header"
+    );
+}
+
+/// Asserts that a response wasn't rejected with
+/// `429 Too Many Requests`.
+///
+/// Rate limiters that use another
+/// [reject_status](RateLimit::reject_status) can use
+/// [assert_not_limited_with].
+///
+pub fn assert_not_limited<R: TestResponse>(response: &R) {
+    assert_not_limited_with(response, Status::TooManyRequests);
+}
+
+/// Asserts that a response wasn't rejected with `status`.
+pub fn assert_not_limited_with<R: TestResponse>(
+    response: &R,
+    status: Status,
+) {
+    assert_ne!(
+        response.status(),
+        status,
+        "the request was rate limited"
+    );
+}
+
+/// Creates a rate limiter whose time only passes when the returned
+/// clock is advanced.
+///
+/// The limiters of its configs have to use the same clock, e.g.
+/// with [fake_limiter].
+///
+pub fn with_fake_clock<K, S>(
+) -> (RateLimit<K, S, FakeRelativeClock>, FakeRelativeClock)
+where
+    K: Eq + Clone + Hash,
+    S: KeyedStateStore<K>,
+{
+    let clock = FakeRelativeClock::default();
+
+    (RateLimit::with_clock(HashMap::new(), clock.clone()), clock)
+}
+
+/// Creates a keyed limiter for `quota` that uses a fake clock.
+pub fn fake_limiter<K>(
+    quota: Quota,
+    clock: &FakeRelativeClock,
+) -> RateLimiter<K, DefaultKeyedStateStore<K>, FakeRelativeClock>
+where
+    K: Eq + Clone + Hash,
+{
+    RateLimiter::new(quota, DefaultKeyedStateStore::default(), clock)
+}
@@ -0,0 +1,201 @@
+use crate::Limiter;
+use governor::clock::Clock;
+use governor::middleware::StateInformationMiddleware;
+use governor::state::keyed::KeyedStateStore;
+use governor::{Quota, RateLimiter};
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::sync::{Arc, RwLock};
+
+/// Chooses the quota of a key, see [RateLimitConfig::tiered].
+///
+/// Implemented for closures, and for [QuotaTable]:
+///
+/// ```no_run
+///# use governor::Quota;
+///# use rocket_rate_limit::QuotaProvider;
+///# use std::num::NonZeroU32;
+/// struct Plans;
+///
+/// impl QuotaProvider<String> for Plans {
+///     fn quota_for(&self, key: &String) -> Quota {
+///         let per_minute = if key.starts_with("pro_") { 600 } else { 60 };
+///         Quota::per_minute(NonZeroU32::new(per_minute).unwrap())
+///     }
+/// }
+/// ```
+///
+/// [RateLimitConfig::tiered]: crate::RateLimitConfig::tiered
+///
+pub trait QuotaProvider<K> {
+    fn quota_for(&self, key: &K) -> Quota;
+}
+
+impl<K, F> QuotaProvider<K> for F
+where
+    F: Fn(&K) -> Quota,
+{
+    fn quota_for(&self, key: &K) -> Quota {
+        self(key)
+    }
+}
+
+/// A default quota, with overrides for some keys.
+///
+/// Useful when a few known keys, e.g. the API keys of paying
+/// customers, get higher limits:
+///
+/// ```no_run
+///# use governor::Quota;
+///# use rocket_rate_limit::QuotaTable;
+///# use std::num::NonZeroU32;
+/// let free = Quota::per_minute(NonZeroU32::new(60).unwrap());
+/// let gold = Quota::per_minute(NonZeroU32::new(600).unwrap());
+///
+/// let table = QuotaTable::new(free)
+///     .with("acme-key".to_string(), gold)
+///     .with("initech-key".to_string(), gold);
+/// ```
+///
+pub struct QuotaTable<K> {
+    default: Quota,
+    overrides: HashMap<K, Quota>,
+}
+
+impl<K: Eq + Hash> QuotaTable<K> {
+    pub fn new(default: Quota) -> Self {
+        QuotaTable {
+            default,
+            overrides: HashMap::new(),
+        }
+    }
+
+    /// Gives `key` its own quota.
+    pub fn with(mut self, key: K, quota: Quota) -> Self {
+        self.overrides.insert(key, quota);
+        self
+    }
+}
+
+impl<K: Eq + Hash> QuotaProvider<K> for QuotaTable<K> {
+    fn quota_for(&self, key: &K) -> Quota {
+        self.overrides.get(key).copied().unwrap_or(self.default)
+    }
+}
+
+/// One keyed limiter per quota a [QuotaProvider] chooses.
+///
+/// Governor's keyed limiters have a single quota, so keys with the
+/// same quota share a limiter. Limiters are created the first time
+/// their quota is chosen, and dropped by the GC once they're empty.
+///
+pub(crate) struct TieredLimiter<K, S, C>
+where
+    K: Eq + Clone + Hash,
+    S: KeyedStateStore<K>,
+    C: Clock,
+{
+    provider: Box<dyn QuotaProvider<K> + Send + Sync>,
+    /// Creates the stores of new limiters.
+    new_store: fn() -> S,
+    limiters: RwLock<HashMap<Quota, Arc<Limiter<K, S, C>>>>,
+}
+
+impl<K, S, C> TieredLimiter<K, S, C>
+where
+    K: Eq + Clone + Hash,
+    S: KeyedStateStore<K>,
+    C: Clock,
+{
+    pub(crate) fn new(
+        provider: Box<dyn QuotaProvider<K> + Send + Sync>,
+    ) -> Self
+    where
+        S: Default,
+    {
+        TieredLimiter {
+            provider,
+            new_store: S::default,
+            limiters: RwLock::new(HashMap::new()),
+        }
+    }
+
+    /// Returns the limiter for the quota of `key`.
+    pub(crate) fn limiter(
+        &self,
+        key: &K,
+        clock: &C,
+    ) -> Arc<Limiter<K, S, C>> {
+        let quota = self.provider.quota_for(key);
+
+        if let Some(limiter) =
+            self.limiters.read().unwrap().get(&quota)
+        {
+            return limiter.clone();
+        }
+
+        self.limiters
+            .write()
+            .unwrap()
+            .entry(quota)
+            .or_insert_with(|| {
+                Arc::new(
+                    RateLimiter::new(
+                        quota,
+                        (self.new_store)(),
+                        clock,
+                    )
+                    .with_middleware::<StateInformationMiddleware>(),
+                )
+            })
+            .clone()
+    }
+
+    /// The limiters created so far.
+    pub(crate) fn limiters(&self) -> Vec<Arc<Limiter<K, S, C>>> {
+        self.limiters.read().unwrap().values().cloned().collect()
+    }
+
+    /// Sweeps every limiter, and drops the ones `sweep` leaves
+    /// without keys.
+    ///
+    /// Dropped limiters are created again the next time their quota
+    /// is chosen, so memory grows with the tiers in use, not with
+    /// every tier the provider knows.
+    ///
+    pub(crate) fn sweep(&self, sweep: fn(&Limiter<K, S, C>) -> bool) {
+        let limiters: Vec<_> = self
+            .limiters
+            .read()
+            .unwrap()
+            .iter()
+            .map(|(quota, limiter)| (*quota, limiter.clone()))
+            .collect();
+
+        let idle: Vec<Quota> = limiters
+            .into_iter()
+            .filter_map(|(quota, limiter)| {
+                sweep(&limiter).then_some(quota)
+            })
+            .collect();
+
+        if idle.is_empty() {
+            return;
+        }
+
+        let mut limiters = self.limiters.write().unwrap();
+
+        for quota in idle {
+            // Checks hold a clone of the limiter they use, and may
+            // have added keys since the first sweep.
+            let unused =
+                limiters.get(&quota).is_some_and(|limiter| {
+                    Arc::strong_count(limiter) == 1 && sweep(limiter)
+                });
+
+            if unused {
+                limiters.remove(&quota);
+            }
+        }
+    }
+}
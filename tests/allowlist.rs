@@ -0,0 +1,91 @@
+mod common;
+
+use common::{client, limit_with, per_minute};
+use governor::state::keyed::DefaultKeyedStateStore;
+use rocket::http::Status;
+use rocket_rate_limit::testing::{
+    assert_limited, fake_limiter, with_fake_clock,
+};
+use rocket_rate_limit::{
+    ConcurrencyLimit, IpAddrKeyFilter, IpKeyFilter, RateLimit,
+    RateLimitConfig,
+};
+use std::net::{IpAddr, SocketAddr};
+
+fn addr(ip: &str) -> SocketAddr {
+    format!("{}:8000", ip).parse().unwrap()
+}
+
+#[test]
+fn allowlisted_keys_are_never_limited() {
+    let (mut rate_limit, _) = limit_with("search", |limiter| {
+        RateLimitConfig::new(limiter, Box::new(IpKeyFilter))
+    });
+    rate_limit.allow("10.0.0.1".to_string());
+
+    let client = client(rate_limit);
+
+    for _ in 0..1000 {
+        let response =
+            client.get("/search").remote(addr("10.0.0.1")).dispatch();
+        assert_eq!(response.status(), Status::Ok);
+    }
+
+    client.get("/search").remote(addr("10.0.0.2")).dispatch();
+    assert_limited(
+        &client.get("/search").remote(addr("10.0.0.2")).dispatch(),
+    );
+}
+
+#[test]
+fn allowed_networks_skip_concurrency_limits() {
+    let mut rate_limit =
+        RateLimit::<IpAddr, DefaultKeyedStateStore<IpAddr>>::default(
+        );
+
+    // No permits at all, so only exempt requests get through.
+    rate_limit.add_concurrency(
+        "search",
+        vec![ConcurrencyLimit::new(0, Box::new(IpAddrKeyFilter))],
+    );
+    rate_limit.allow_network("192.168.1.0/24".parse().unwrap());
+
+    let client = client(rate_limit);
+
+    let response =
+        client.get("/search").remote(addr("192.168.1.7")).dispatch();
+    assert_eq!(response.status(), Status::Ok);
+
+    assert_limited(
+        &client.get("/search").remote(addr("10.0.0.2")).dispatch(),
+    );
+}
+
+#[test]
+fn allowed_networks_are_never_limited() {
+    let (mut rate_limit, clock) =
+        with_fake_clock::<IpAddr, DefaultKeyedStateStore<IpAddr>>();
+
+    rate_limit.add(
+        "search",
+        vec![RateLimitConfig::new(
+            fake_limiter(per_minute(1), &clock),
+            Box::new(IpAddrKeyFilter),
+        )],
+    );
+    rate_limit.allow_network("192.168.1.0/24".parse().unwrap());
+
+    let client = client(rate_limit);
+
+    for host in 0..100 {
+        let ip = format!("192.168.1.{}", host % 10);
+        let response =
+            client.get("/search").remote(addr(&ip)).dispatch();
+        assert_eq!(response.status(), Status::Ok);
+    }
+
+    client.get("/search").remote(addr("10.0.0.2")).dispatch();
+    assert_limited(
+        &client.get("/search").remote(addr("10.0.0.2")).dispatch(),
+    );
+}
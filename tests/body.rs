@@ -0,0 +1,43 @@
+mod common;
+
+use rocket::data::{Data, ToByteUnit};
+use rocket::http::Status;
+use rocket::local::asynchronous::Client;
+use rocket::{post, routes};
+use rocket_rate_limit::testing::assert_limited;
+
+#[post("/upload", data = "<data>")]
+async fn upload(data: Data<'_>) -> Vec<u8> {
+    data.open(2.mebibytes())
+        .into_bytes()
+        .await
+        .unwrap()
+        .into_inner()
+}
+
+#[rocket::async_test]
+async fn bodies_reach_the_handler_intact() {
+    let (rate_limit, _) = common::limit("upload");
+
+    let client = Client::tracked(
+        rocket::build()
+            .mount("/", routes![upload])
+            .attach(rate_limit),
+    )
+    .await
+    .unwrap();
+
+    let body: Vec<u8> =
+        (0..1024 * 1024).map(|i| (i % 251) as u8).collect();
+
+    let response =
+        client.post("/upload").body(&body).dispatch().await;
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(response.into_bytes().await.unwrap(), body);
+
+    // The rejection is rerouted to the internal route, which must
+    // not choke on the body it doesn't read either.
+    let response =
+        client.post("/upload").body(&body).dispatch().await;
+    assert_limited(&response);
+}
@@ -0,0 +1,68 @@
+mod common;
+
+use common::{client, everyone, Limit};
+use governor::clock::FakeRelativeClock;
+use governor::state::keyed::DefaultKeyedStateStore;
+use governor::{Quota, RateLimiter};
+use rocket::http::Status;
+use rocket_rate_limit::testing::assert_limited;
+use rocket_rate_limit::{RateLimit, RateLimitConfig};
+use std::collections::HashMap;
+use std::num::NonZeroU32;
+use std::time::Duration;
+
+#[test]
+fn limited_keys_are_allowed_once_the_clock_advances() {
+    let clock = FakeRelativeClock::default();
+    let mut rate_limit: Limit =
+        RateLimit::with_clock(HashMap::new(), clock.clone());
+
+    rate_limit.add(
+        "search",
+        vec![RateLimitConfig::new(
+            RateLimiter::new(
+                Quota::per_second(NonZeroU32::new(1).unwrap()),
+                DefaultKeyedStateStore::default(),
+                &clock,
+            ),
+            everyone(),
+        )],
+    );
+
+    let client = client(rate_limit);
+
+    assert_eq!(client.get("/search").dispatch().status(), Status::Ok);
+    assert_limited(&client.get("/search").dispatch());
+
+    // Real time doesn't refill the bucket.
+    std::thread::sleep(Duration::from_millis(1100));
+    assert_limited(&client.get("/search").dispatch());
+
+    clock.advance(Duration::from_secs(1));
+    assert_eq!(client.get("/search").dispatch().status(), Status::Ok);
+}
+
+#[test]
+fn sliding_windows_use_the_clock_of_the_rate_limiter() {
+    let clock = FakeRelativeClock::default();
+    let mut rate_limit: Limit =
+        RateLimit::with_clock(HashMap::new(), clock.clone());
+
+    rate_limit.add(
+        "search",
+        vec![RateLimitConfig::sliding_window_with_clock(
+            NonZeroU32::new(1).unwrap(),
+            Duration::from_secs(60),
+            everyone(),
+            &clock,
+        )],
+    );
+
+    let client = client(rate_limit);
+
+    assert_eq!(client.get("/search").dispatch().status(), Status::Ok);
+    assert_limited(&client.get("/search").dispatch());
+
+    clock.advance(Duration::from_secs(61));
+    assert_eq!(client.get("/search").dispatch().status(), Status::Ok);
+}
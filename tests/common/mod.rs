@@ -0,0 +1,111 @@
+//! Fixtures shared by the integration tests.
+//!
+//! Every test binary compiles the module on its own, and none of
+//! them uses all of it.
+#![allow(dead_code)]
+
+use governor::clock::FakeRelativeClock;
+use governor::state::keyed::DefaultKeyedStateStore;
+use governor::{Quota, RateLimiter};
+use rocket::fairing::Fairing;
+use rocket::local::blocking::Client;
+use rocket::{get, routes, Build, Rocket};
+use rocket_rate_limit::testing::{fake_limiter, with_fake_clock};
+use rocket_rate_limit::{
+    ConstantKeyFilter, KeyFilter, RateLimit, RateLimitConfig,
+};
+use std::num::NonZeroU32;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+pub type Limit = RateLimit<
+    String,
+    DefaultKeyedStateStore<String>,
+    FakeRelativeClock,
+>;
+pub type Limiter = RateLimiter<
+    String,
+    DefaultKeyedStateStore<String>,
+    FakeRelativeClock,
+>;
+pub type Config = RateLimitConfig<
+    String,
+    DefaultKeyedStateStore<String>,
+    FakeRelativeClock,
+>;
+
+#[get("/search")]
+pub fn search() -> &'static str {
+    "results"
+}
+
+/// Allows `burst` requests per minute.
+pub fn per_minute(burst: u32) -> Quota {
+    Quota::per_minute(NonZeroU32::new(burst).unwrap())
+}
+
+/// Uses the same key for every request.
+pub fn everyone() -> Box<dyn KeyFilter<String> + Send + Sync> {
+    Box::new(ConstantKeyFilter::new("everyone".to_string()))
+}
+
+/// A rate limiter with a fake clock and a config for `route_name`,
+/// which `config` creates from a limiter of one request per minute.
+pub fn limit_with(
+    route_name: &str,
+    config: impl FnOnce(Limiter) -> Config,
+) -> (Limit, FakeRelativeClock) {
+    let (mut rate_limit, clock) =
+        with_fake_clock::<String, DefaultKeyedStateStore<String>>();
+
+    rate_limit.add(
+        route_name,
+        vec![config(fake_limiter(per_minute(1), &clock))],
+    );
+
+    (rate_limit, clock)
+}
+
+/// A rate limiter that allows one request per minute to
+/// `route_name`, shared by everyone.
+pub fn limit(route_name: &str) -> (Limit, FakeRelativeClock) {
+    limit_with(route_name, |limiter| {
+        RateLimitConfig::new(limiter, everyone())
+    })
+}
+
+/// A client for `rocket` with `rate_limit` attached.
+pub fn client_for(
+    rocket: Rocket<Build>,
+    rate_limit: impl Fairing,
+) -> Client {
+    Client::tracked(rocket.attach(rate_limit)).unwrap()
+}
+
+/// A client for [search] with `rate_limit` attached.
+pub fn client(rate_limit: impl Fairing) -> Client {
+    client_for(
+        rocket::build().mount("/", routes![search]),
+        rate_limit,
+    )
+}
+
+/// Number of requests that reached a handler, for handlers that
+/// count themselves.
+#[derive(Default)]
+pub struct Calls(pub AtomicUsize);
+
+impl Calls {
+    pub fn count(&self) {
+        self.0.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// Number of requests that reached the handlers of `client`.
+pub fn calls(client: &Client) -> usize {
+    client
+        .rocket()
+        .state::<Calls>()
+        .unwrap()
+        .0
+        .load(Ordering::Relaxed)
+}
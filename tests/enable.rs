@@ -0,0 +1,27 @@
+mod common;
+
+use common::{client, limit};
+use rocket::http::Status;
+use rocket_rate_limit::testing::assert_limited;
+use std::sync::Arc;
+
+#[test]
+fn disabled_rate_limits_let_requests_through() {
+    let (rate_limit, _) = limit("search");
+    let rate_limit = Arc::new(rate_limit);
+    let client = client(rate_limit.clone());
+
+    assert_eq!(client.get("/search").dispatch().status(), Status::Ok);
+    assert_limited(&client.get("/search").dispatch());
+
+    rate_limit.disable();
+    assert!(!rate_limit.is_enabled());
+
+    for _ in 0..10 {
+        let response = client.get("/search").dispatch();
+        assert_eq!(response.status(), Status::Ok);
+    }
+
+    rate_limit.enable();
+    assert_limited(&client.get("/search").dispatch());
+}
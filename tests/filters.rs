@@ -0,0 +1,88 @@
+mod common;
+
+use common::{limit_with, Config, Limiter};
+use rocket::http::Status;
+use rocket::local::blocking::Client;
+use rocket_rate_limit::testing::assert_limited;
+use rocket_rate_limit::{
+    IpKeyFilter, KeyFilter, MissingIpPolicy, QueryKeyFilter,
+    RateLimitConfig,
+};
+use std::net::SocketAddr;
+
+/// A client for a `search` route limited to one request per minute
+/// and key.
+fn client<F>(filter: F) -> Client
+where
+    F: KeyFilter<String> + Send + Sync + 'static,
+{
+    client_with(|limiter| {
+        RateLimitConfig::new(limiter, Box::new(filter))
+    })
+}
+
+/// Like [client], with the config `config` creates from the limiter.
+fn client_with(config: impl FnOnce(Limiter) -> Config) -> Client {
+    common::client(limit_with("search", config).0)
+}
+
+#[test]
+fn query_values_have_separate_buckets() {
+    let client = client(QueryKeyFilter::new("tenant"));
+
+    let response = client.get("/search?tenant=a").dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    assert_limited(&client.get("/search?tenant=a").dispatch());
+
+    let response = client.get("/search?tenant=b").dispatch();
+    assert_eq!(response.status(), Status::Ok);
+}
+
+#[test]
+fn query_values_are_decoded_and_repeats_use_the_first() {
+    let client = client(QueryKeyFilter::new("tenant"));
+
+    client.get("/search?tenant=a%2Fb&tenant=c").dispatch();
+    assert_limited(&client.get("/search?tenant=a/b").dispatch());
+
+    let response = client.get("/search?tenant=c").dispatch();
+    assert_eq!(response.status(), Status::Ok);
+}
+
+#[test]
+fn missing_or_empty_query_values_skip_the_config() {
+    let client = client(QueryKeyFilter::new("tenant"));
+
+    for uri in
+        ["/search", "/search?tenant=", "/search", "/search?tenant="]
+    {
+        let response = client.get(uri).dispatch();
+        assert_eq!(response.status(), Status::Ok);
+    }
+}
+
+#[test]
+fn ipv4_mapped_addresses_share_the_ipv4_bucket() {
+    let client = client(IpKeyFilter);
+    let v4: SocketAddr = "1.2.3.4:8000".parse().unwrap();
+    let mapped: SocketAddr = "[::ffff:1.2.3.4]:8000".parse().unwrap();
+
+    let response = client.get("/search").remote(v4).dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    assert_limited(&client.get("/search").remote(mapped).dispatch());
+}
+
+#[test]
+fn requests_without_an_ip_are_rejected_by_the_reject_policy() {
+    // The config isn't fail closed, the policy rejects anyway.
+    let client = client_with(|limiter| {
+        RateLimitConfig::try_new(
+            limiter,
+            Box::new(IpKeyFilter::on_missing(
+                MissingIpPolicy::Reject,
+            )),
+        )
+    });
+
+    assert_limited(&client.get("/search").dispatch());
+}
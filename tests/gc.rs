@@ -0,0 +1,51 @@
+mod common;
+
+use common::limit_with;
+use rocket::local::asynchronous::Client;
+use rocket::routes;
+use rocket::tokio::time::sleep;
+use rocket_rate_limit::{QueryKeyFilter, RateLimitConfig};
+use std::sync::Arc;
+use std::time::Duration;
+
+#[rocket::async_test]
+async fn sweeps_remove_stale_keys() {
+    let (rate_limit, clock) = limit_with("search", |limiter| {
+        RateLimitConfig::new(
+            limiter,
+            Box::new(QueryKeyFilter::new("user")),
+        )
+    });
+
+    let rate_limit =
+        Arc::new(rate_limit.gc_interval(Duration::from_millis(10)));
+
+    let client = Client::tracked(
+        rocket::build()
+            .mount("/", routes![common::search])
+            .attach(rate_limit.clone()),
+    )
+    .await
+    .unwrap();
+
+    for user in 0..100 {
+        client
+            .get(format!("/search?user={}", user))
+            .dispatch()
+            .await;
+    }
+    assert_eq!(rate_limit.key_count("search"), vec![100]);
+
+    // Every bucket is full again, so no key affects the limits.
+    clock.advance(Duration::from_secs(2 * 60));
+
+    // The sweep runs on the runtime of the client.
+    for _ in 0..100 {
+        if rate_limit.key_count("search") == vec![0] {
+            break;
+        }
+
+        sleep(Duration::from_millis(10)).await;
+    }
+    assert_eq!(rate_limit.key_count("search"), vec![0]);
+}
@@ -0,0 +1,45 @@
+mod common;
+
+use common::{client, everyone, per_minute, Config};
+use governor::state::keyed::DefaultKeyedStateStore;
+use rocket_rate_limit::testing::{fake_limiter, with_fake_clock};
+use rocket_rate_limit::RateLimitConfig;
+
+/// The `X-RateLimit-Limit` of a search, with each config added in a
+/// separate call. The burst of each config tells which one is
+/// reported, i.e. checked first.
+fn reported_limit(configs: Vec<(u32, u32)>) -> String {
+    let (mut rate_limit, clock) =
+        with_fake_clock::<String, DefaultKeyedStateStore<String>>();
+
+    for (burst, priority) in configs {
+        let config: Config = RateLimitConfig::new(
+            fake_limiter(per_minute(burst), &clock),
+            everyone(),
+        )
+        .priority(priority);
+
+        rate_limit.add("search", vec![config]);
+    }
+
+    let client = client(rate_limit.headers(true));
+
+    let response = client.get("/search").dispatch();
+    response
+        .headers()
+        .get_one("X-RateLimit-Limit")
+        .unwrap()
+        .to_string()
+}
+
+#[test]
+fn equal_priorities_are_checked_in_the_order_they_were_added() {
+    assert_eq!(reported_limit(vec![(5, 0), (10, 0)]), "5");
+    assert_eq!(reported_limit(vec![(10, 0), (5, 0)]), "10");
+}
+
+#[test]
+fn higher_priorities_are_checked_first() {
+    assert_eq!(reported_limit(vec![(5, 0), (10, 1)]), "10");
+    assert_eq!(reported_limit(vec![(10, 1), (5, 0)]), "10");
+}
@@ -0,0 +1,42 @@
+mod common;
+
+use common::{client, everyone};
+use governor::state::keyed::DefaultKeyedStateStore;
+use governor::{Quota, RateLimiter};
+use rocket::http::Status;
+use rocket_rate_limit::testing::{assert_limited, with_fake_clock};
+use rocket_rate_limit::{RateLimitConfig, SharedStore};
+use std::num::NonZeroU32;
+use std::sync::Arc;
+
+type Store = SharedStore<DefaultKeyedStateStore<String>>;
+
+#[test]
+fn reset_keys_start_with_a_full_burst() {
+    let (mut rate_limit, clock) = with_fake_clock::<String, Store>();
+    let store = Store::default();
+
+    rate_limit.add(
+        "search",
+        vec![RateLimitConfig::new(
+            RateLimiter::new(
+                Quota::per_hour(NonZeroU32::new(1).unwrap()),
+                store.clone(),
+                &clock,
+            ),
+            everyone(),
+        )
+        .resettable(&store)],
+    );
+
+    let rate_limit = Arc::new(rate_limit);
+    let client = client(rate_limit.clone());
+
+    assert_eq!(client.get("/search").dispatch().status(), Status::Ok);
+    assert_limited(&client.get("/search").dispatch());
+
+    rate_limit.reset("search", &"everyone".to_string());
+
+    assert_eq!(client.get("/search").dispatch().status(), Status::Ok);
+    assert_limited(&client.get("/search").dispatch());
+}
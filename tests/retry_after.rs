@@ -0,0 +1,77 @@
+mod common;
+
+use common::{client, everyone, Limit};
+use governor::state::keyed::DefaultKeyedStateStore;
+use governor::Quota;
+use rocket_rate_limit::testing::{
+    assert_limited, fake_limiter, with_fake_clock,
+};
+use rocket_rate_limit::{ConcurrencyLimit, RateLimitConfig};
+use std::time::Duration;
+
+/// A rate limit allowing one search per `period`.
+fn rate_limit(period: Duration) -> Limit {
+    let (mut rate_limit, clock) =
+        with_fake_clock::<String, DefaultKeyedStateStore<String>>();
+
+    rate_limit.add(
+        "search",
+        vec![RateLimitConfig::new(
+            fake_limiter(Quota::with_period(period).unwrap(), &clock),
+            everyone(),
+        )],
+    );
+
+    rate_limit
+}
+
+#[test]
+fn sub_second_waits_round_up_to_one_second() {
+    let client = client(rate_limit(Duration::from_millis(500)));
+
+    client.get("/search").dispatch();
+
+    let response = client.get("/search").dispatch();
+    assert_limited(&response);
+    assert_eq!(response.headers().get_one("Retry-After"), Some("1"));
+}
+
+#[test]
+fn reset_header_uses_the_advertised_wait() {
+    let client = client(
+        rate_limit(Duration::from_secs(1))
+            .headers(true)
+            .retry_after_bounds(
+                Duration::from_secs(10),
+                Duration::from_secs(60),
+            ),
+    );
+
+    client.get("/search").dispatch();
+
+    let response = client.get("/search").dispatch();
+    assert_limited(&response);
+    assert_eq!(response.headers().get_one("Retry-After"), Some("10"));
+    assert_eq!(
+        response.headers().get_one("X-RateLimit-Reset"),
+        Some("10")
+    );
+}
+
+#[test]
+fn concurrency_rejections_wait_at_least_one_second() {
+    let (mut rate_limit, _) =
+        with_fake_clock::<String, DefaultKeyedStateStore<String>>();
+
+    // No permits and no timeout, so requests are rejected at once.
+    rate_limit.add_concurrency(
+        "search",
+        vec![ConcurrencyLimit::new(0, everyone())],
+    );
+
+    let client = client(rate_limit);
+
+    let response = client.get("/search").dispatch();
+    assert_limited(&response);
+    assert_eq!(response.headers().get_one("Retry-After"), Some("1"));
+}
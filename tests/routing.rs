@@ -0,0 +1,66 @@
+mod common;
+
+use common::{calls, limit, Calls};
+use rocket::http::Status;
+use rocket::local::blocking::Client;
+use rocket::{get, routes, Build, Rocket, State};
+use rocket_rate_limit::testing::assert_limited;
+
+#[get("/search")]
+fn search(calls: &State<Calls>) -> &'static str {
+    calls.count();
+    "results"
+}
+
+/// A client for `rocket`, which allows one request per minute to
+/// `search`.
+fn client(rocket: Rocket<Build>) -> Client {
+    common::client_for(
+        rocket.manage(Calls::default()),
+        limit("search").0,
+    )
+}
+
+#[test]
+fn rejected_requests_reach_the_internal_route() {
+    let client = client(rocket::build().mount("/", routes![search]));
+
+    assert_eq!(client.get("/search").dispatch().status(), Status::Ok);
+
+    let response = client.get("/search").dispatch();
+    assert_limited(&response);
+    assert_eq!(response.into_string().as_deref(), Some(""));
+    assert_eq!(calls(&client), 1);
+}
+
+#[test]
+fn routes_mounted_under_a_base_are_limited() {
+    let client =
+        client(rocket::build().mount("/api", routes![search]));
+
+    let response = client.get("/api/search").dispatch();
+    assert_eq!(response.status(), Status::Ok);
+
+    let response = client.get("/api/search").dispatch();
+    assert_limited(&response);
+    assert_eq!(calls(&client), 1);
+}
+
+#[test]
+fn head_requests_share_the_get_bucket() {
+    let client = client(rocket::build().mount("/", routes![search]));
+
+    assert_eq!(client.get("/search").dispatch().status(), Status::Ok);
+    assert_limited(&client.head("/search").dispatch());
+}
+
+#[test]
+fn get_requests_share_the_head_bucket() {
+    let client = client(rocket::build().mount("/", routes![search]));
+
+    assert_eq!(
+        client.head("/search").dispatch().status(),
+        Status::Ok
+    );
+    assert_limited(&client.get("/search").dispatch());
+}
@@ -0,0 +1,137 @@
+mod common;
+
+use common::{client_for, limit, per_minute};
+use governor::state::keyed::DefaultKeyedStateStore;
+use governor::Quota;
+use rocket::http::Status;
+use rocket::{catch, catchers, get, routes};
+use rocket_rate_limit::testing::{
+    assert_limited, assert_limited_with, assert_not_limited_with,
+    fake_limiter, with_fake_clock,
+};
+use rocket_rate_limit::{
+    NotRateLimited, QueryKeyFilter, RateLimitConfig,
+};
+use std::num::NonZeroU32;
+
+#[get("/search")]
+fn search(_guard: NotRateLimited) -> &'static str {
+    "results"
+}
+
+#[get("/feed")]
+fn feed() -> &'static str {
+    "entries"
+}
+
+#[catch(403)]
+fn forbidden() -> &'static str {
+    "slow down"
+}
+
+#[catch(429)]
+fn too_many_requests() -> &'static str {
+    "throttled"
+}
+
+#[catch(503)]
+fn service_unavailable() -> &'static str {
+    "overloaded"
+}
+
+#[test]
+fn the_guard_fails_with_the_reject_status() {
+    let (rate_limit, _) = limit("search");
+
+    let client = client_for(
+        rocket::build()
+            .mount("/", routes![search])
+            .register("/", catchers![forbidden]),
+        rate_limit
+            .redirect(false)
+            .catchers(true)
+            .reject_status(Status::Forbidden),
+    );
+
+    let response = client.get("/search").dispatch();
+    assert_not_limited_with(&response, Status::Forbidden);
+
+    // The catcher of the guard's status renders the rejection.
+    let response = client.get("/search").dispatch();
+    assert_limited_with(&response, Status::Forbidden);
+    assert_eq!(response.into_string().as_deref(), Some("slow down"));
+}
+
+#[test]
+fn routes_without_the_guard_get_the_rejection() {
+    let (rate_limit, _) = limit("feed");
+
+    let client = client_for(
+        rocket::build()
+            .mount("/", routes![feed])
+            .register("/", catchers![forbidden]),
+        rate_limit.redirect(false).catchers(true),
+    );
+
+    let response = client.get("/feed").dispatch();
+    assert_eq!(response.status(), Status::Ok);
+
+    // No guard fails, so the handler's reply is replaced.
+    let response = client.get("/feed").dispatch();
+    assert_limited(&response);
+    assert_ne!(response.into_string().as_deref(), Some("entries"));
+}
+
+#[test]
+fn configs_reject_with_their_own_status() {
+    let (mut rate_limit, clock) =
+        with_fake_clock::<String, DefaultKeyedStateStore<String>>();
+
+    rate_limit.add(
+        "search",
+        vec![
+            RateLimitConfig::new(
+                fake_limiter(per_minute(1), &clock),
+                Box::new(QueryKeyFilter::new("user")),
+            )
+            .reject_status(Status::TooManyRequests),
+            // Waits longer than the config above once both reject.
+            RateLimitConfig::new(
+                fake_limiter(
+                    Quota::per_hour(NonZeroU32::new(1).unwrap()),
+                    &clock,
+                ),
+                Box::new(QueryKeyFilter::new("region")),
+            )
+            .reject_status(Status::ServiceUnavailable),
+        ],
+    );
+
+    let client = client_for(
+        rocket::build().mount("/", routes![search]).register(
+            "/",
+            catchers![too_many_requests, service_unavailable],
+        ),
+        rate_limit.redirect(false).catchers(true),
+    );
+
+    let response = client.get("/search?user=a").dispatch();
+    assert_eq!(response.status(), Status::Ok);
+
+    // The guard fails with the status of the rejecting config, so
+    // its catcher renders the response.
+    let response = client.get("/search?user=a").dispatch();
+    assert_limited_with(&response, Status::TooManyRequests);
+    assert_eq!(response.into_string().as_deref(), Some("throttled"));
+
+    let response = client.get("/search?region=eu").dispatch();
+    assert_eq!(response.status(), Status::Ok);
+
+    let response = client.get("/search?region=eu").dispatch();
+    assert_limited_with(&response, Status::ServiceUnavailable);
+    assert_eq!(response.into_string().as_deref(), Some("overloaded"));
+
+    let response = client.get("/search?user=a&region=eu").dispatch();
+    assert_limited_with(&response, Status::ServiceUnavailable);
+    assert_eq!(response.into_string().as_deref(), Some("overloaded"));
+}
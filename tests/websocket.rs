@@ -0,0 +1,89 @@
+mod common;
+
+use common::{calls, limit, Calls, Limit};
+use rocket::data::{IoHandler, IoStream};
+use rocket::http::Status;
+use rocket::local::blocking::{Client, LocalResponse};
+use rocket::response::{self, Responder, Response};
+use rocket::{get, routes, Request, State};
+use rocket_rate_limit::testing::assert_limited;
+use std::io;
+use std::pin::Pin;
+
+/// Accepts a WebSocket handshake, like `rocket_ws` does.
+struct Handshake;
+
+impl<'r> Responder<'r, 'static> for Handshake {
+    fn respond_to(
+        self,
+        _: &'r Request<'_>,
+    ) -> response::Result<'static> {
+        Response::build()
+            .status(Status::SwitchingProtocols)
+            .raw_header("Connection", "Upgrade")
+            .raw_header("Upgrade", "websocket")
+            .upgrade("websocket", Echo)
+            .ok()
+    }
+}
+
+struct Echo;
+
+#[rocket::async_trait]
+impl IoHandler for Echo {
+    async fn io(
+        self: Pin<Box<Self>>,
+        _io: IoStream,
+    ) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[get("/ws")]
+fn ws(calls: &State<Calls>) -> Handshake {
+    calls.count();
+    Handshake
+}
+
+/// A client whose rate limiter allows one handshake per minute.
+fn client(configure: impl FnOnce(Limit) -> Limit) -> Client {
+    common::client_for(
+        rocket::build()
+            .manage(Calls::default())
+            .mount("/", routes![ws]),
+        configure(limit("ws").0),
+    )
+}
+
+fn handshake(client: &Client) -> LocalResponse<'_> {
+    client
+        .get("/ws")
+        .raw_header("Connection", "Upgrade")
+        .raw_header("Upgrade", "websocket")
+        .dispatch()
+}
+
+#[test]
+fn throttled_handshakes_never_reach_the_handler() {
+    let client = client(|rate_limit| rate_limit.redirect(true));
+
+    let response = handshake(&client);
+    assert_eq!(response.status(), Status::SwitchingProtocols);
+
+    let response = handshake(&client);
+    assert_limited(&response);
+    assert_eq!(calls(&client), 1);
+}
+
+#[test]
+fn throttled_handshakes_are_rejected_without_redirects() {
+    let client = client(|rate_limit| rate_limit.redirect(false));
+
+    let response = handshake(&client);
+    assert_eq!(response.status(), Status::SwitchingProtocols);
+
+    // The handler accepts the handshake, the rejection replaces it.
+    let response = handshake(&client);
+    assert_limited(&response);
+    assert_eq!(calls(&client), 2);
+}